@@ -1,10 +1,15 @@
-use crate::hash::Hash;
+use super::vm::Vm;
+use crate::hash::{BatchHash, Hash};
+use std::cell::OnceCell;
 use std::fmt;
 
 #[derive(Debug)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
     pub result: Value,
+    /// the program verified and decoded the first time it is hashed, so the instructions
+    /// shouldn't change after that
+    vm: OnceCell<Vm>,
 }
 
 pub type Memory = usize;
@@ -26,41 +31,52 @@ pub enum Instruction {
 }
 
 impl Program {
+    pub fn new(instructions: Vec<Instruction>, result: Value) -> Program {
+        Program {
+            instructions,
+            result,
+            vm: OnceCell::new(),
+        }
+    }
+
+    /// run a program on a single byte without verifying it first, see `Program::verify`
+    // rotation amounts are truncated on purpose, only the low 6 bits matter
+    #[allow(clippy::cast_possible_truncation)]
     pub fn eval(&self, hash_state: u64, byte: u8) -> u64 {
         let mut mem = vec![0u64; 2.max(self.biggest_ptr() + 1)];
         mem[0] = hash_state;
-        mem[1] = byte as u64;
+        mem[1] = u64::from(byte);
 
         for instr in &self.instructions {
             match instr {
-                Instruction::Move(dst, Value::Immediate(val)) => mem[*dst] = *val as u64,
+                Instruction::Move(dst, Value::Immediate(val)) => mem[*dst] = u64::from(*val),
                 Instruction::Move(dst, Value::Reference(src)) => mem[*dst] = mem[*src],
                 Instruction::MoveAbs(dst, val) => mem[*dst] = *val,
                 Instruction::Add(dst, Value::Immediate(val)) => {
-                    mem[*dst] = mem[*dst].wrapping_add(*val as u64)
+                    mem[*dst] = mem[*dst].wrapping_add(u64::from(*val));
                 }
                 Instruction::Add(dst, Value::Reference(src)) => {
-                    mem[*dst] = mem[*dst].wrapping_add(mem[*src])
+                    mem[*dst] = mem[*dst].wrapping_add(mem[*src]);
                 }
-                Instruction::Xor(dst, Value::Immediate(val)) => mem[*dst] ^= *val as u64,
+                Instruction::Xor(dst, Value::Immediate(val)) => mem[*dst] ^= u64::from(*val),
                 Instruction::Xor(dst, Value::Reference(src)) => mem[*dst] ^= mem[*src],
                 Instruction::RotLeft(dst, Value::Immediate(val)) => {
-                    mem[*dst] = mem[*dst].rotate_left(*val as u32)
+                    mem[*dst] = mem[*dst].rotate_left(*val);
                 }
                 Instruction::RotLeft(dst, Value::Reference(src)) => {
-                    mem[*dst] = mem[*dst].rotate_left(mem[*src] as u32)
+                    mem[*dst] = mem[*dst].rotate_left(mem[*src] as u32);
                 }
                 Instruction::RotRight(dst, Value::Immediate(val)) => {
-                    mem[*dst] = mem[*dst].rotate_right(*val as u32)
+                    mem[*dst] = mem[*dst].rotate_right(*val);
                 }
                 Instruction::RotRight(dst, Value::Reference(src)) => {
-                    mem[*dst] = mem[*dst].rotate_right(mem[*src] as u32)
+                    mem[*dst] = mem[*dst].rotate_right(mem[*src] as u32);
                 }
             }
         }
 
         match self.result {
-            Value::Immediate(val) => val as u64,
+            Value::Immediate(val) => u64::from(val),
             Value::Reference(idx) => mem[idx],
         }
     }
@@ -82,15 +98,24 @@ impl Program {
 
         biggest
     }
+
+    /// the vm the program is hashed with, verified the first time the program is hashed
+    fn vm(&self) -> &Vm {
+        self.vm.get_or_init(|| {
+            Vm::new(self).unwrap_or_else(|err| panic!("invalid program, {err}\n{self}"))
+        })
+    }
 }
 
 impl Hash for Program {
     fn hash_bytes(&self, init: u64, bytes: &[u8]) -> u64 {
-        let mut hash = init;
-        for byte in bytes {
-            hash = self.eval(hash, *byte);
-        }
-        hash
+        self.vm().hash_bytes(init, bytes)
+    }
+}
+
+impl<const N: usize> BatchHash<N> for Program {
+    fn hash_batch(&self, init: u64, inputs: &[&[u8]; N]) -> [u64; N] {
+        self.vm().hash_batch(init, inputs)
     }
 }
 
@@ -1,4 +1,4 @@
-use super::code::*;
+use super::code::{Instruction, Memory, Program, Value};
 use crate::expr::expr::{Expr, Tag};
 use std::collections::HashSet;
 
@@ -8,19 +8,14 @@ pub fn emit(expr: &Expr<Tag>, registers: usize) -> Program {
     let Program {
         mut instructions,
         result,
+        ..
     } = emit_expr(expr, &mem_idx);
 
     match result {
-        Value::Reference(2) => Program {
-            instructions,
-            result,
-        },
+        Value::Reference(2) => Program::new(instructions, result),
         val => {
             instructions.push(Instruction::Move(2, val));
-            Program {
-                instructions,
-                result: Value::Reference(2),
-            }
+            Program::new(instructions, Value::Reference(2))
         }
     }
 }
@@ -34,35 +29,25 @@ fn emit_expr(expr: &Expr<Tag>, mem_idx: &[usize]) -> Program {
         Expr::RotLeft(a, b) => (Instruction::RotLeft as BinInstruction, a, b),
         Expr::RotRight(a, b) => (Instruction::RotRight as BinInstruction, a, b),
         Expr::Tag(Tag::Const(num)) => {
-            return if let Ok(trunc_num) = (*num).try_into() {
-                Program {
-                    instructions: Vec::new(),
-                    result: Value::Immediate(trunc_num),
+            // immediates are sign extended by the x86 backend, so they have to fit in 31 bits
+            return match u32::try_from(*num) {
+                Ok(trunc_num) if i32::try_from(trunc_num).is_ok() => {
+                    Program::new(Vec::new(), Value::Immediate(trunc_num))
                 }
-            } else {
-                Program {
-                    instructions: vec![Instruction::MoveAbs(mem_idx[0], *num)],
-                    result: Value::Reference(mem_idx[0]),
-                }
-            }
-        }
-        Expr::Tag(Tag::HashState) => {
-            return Program {
-                instructions: Vec::new(),
-                result: Value::Reference(0),
-            }
-        }
-        Expr::Tag(Tag::Byte) => {
-            return Program {
-                instructions: Vec::new(),
-                result: Value::Reference(1),
-            }
+                _ => Program::new(
+                    vec![Instruction::MoveAbs(mem_idx[0], *num)],
+                    Value::Reference(mem_idx[0]),
+                ),
+            };
         }
+        Expr::Tag(Tag::HashState) => return Program::new(Vec::new(), Value::Reference(0)),
+        Expr::Tag(Tag::Byte) => return Program::new(Vec::new(), Value::Reference(1)),
     };
 
     let Program {
         mut instructions,
         result: a_res,
+        ..
     } = emit_expr(a, &mem_idx[1..]);
 
     instructions.push(Instruction::Move(mem_idx[0], a_res));
@@ -70,16 +55,14 @@ fn emit_expr(expr: &Expr<Tag>, mem_idx: &[usize]) -> Program {
     let Program {
         instructions: b_instrs,
         result: b_res,
+        ..
     } = emit_expr(b, &mem_idx[1..]);
 
     instructions.extend(b_instrs);
 
     instructions.push(bin_instr(mem_idx[0], b_res));
 
-    Program {
-        instructions,
-        result: Value::Reference(mem_idx[0]),
-    }
+    Program::new(instructions, Value::Reference(mem_idx[0]))
 }
 
 // allocate the registers for the intermediate results of an expression
@@ -94,7 +77,6 @@ fn register_allocate(expr: &Expr<Tag>, registers: usize) -> Vec<usize> {
         .iter()
         .copied()
         .enumerate()
-        .map(|(level, measure)| (level, measure))
         .collect();
     tagged_levels.sort_by_key(|(_, measure)| *measure);
 
@@ -129,6 +111,6 @@ fn measure_levels(expr: &Expr<Tag>, levels: &mut [usize]) {
             measure_levels(a, &mut levels[1..]);
             measure_levels(b, &mut levels[1..]);
         }
-        Expr::Tag(Tag::Const(_)) | Expr::Tag(Tag::HashState) | Expr::Tag(Tag::Byte) => (),
+        Expr::Tag(Tag::Const(_) | Tag::HashState | Tag::Byte) => (),
     }
 }
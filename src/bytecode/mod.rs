@@ -1,2 +1,8 @@
 pub mod code;
+/// the direct emitter from `Expr` to `Program`, which `main` replaced by lowering through `ssa`,
+/// kept as a reference for differential tests
+#[cfg(test)]
 pub mod gen;
+pub mod parse;
+pub mod verify;
+pub mod vm;
@@ -0,0 +1,197 @@
+use super::code::{Instruction, Memory, Program, Value};
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+/// an error found while parsing, along with the (1 based) line it was found on
+#[derive(Debug)]
+pub struct Error {
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    MissingHeader,
+    ExpectedWhere(String),
+    UnknownInstruction(String),
+    ExpectedMemory(String),
+    ExpectedValue(String),
+    ExpectedNumber(String),
+    ExpectedOperand,
+    ExpectedEol(String),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// parse a program in the same format that it is displayed in, for example
+///
+/// ```text
+/// %2 where
+/// mov %4 %0      # comments run to the end of the line
+/// add %4 $17
+/// mov %2 %4
+/// ```
+pub fn parse(text: &str) -> Result<Program> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, strip_comment(line)))
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let (line, header) = lines.next().ok_or(Error {
+        line: 1,
+        kind: ErrorKind::MissingHeader,
+    })?;
+    let result = parse_header(header).map_err(|kind| Error { line, kind })?;
+
+    let instructions = lines
+        .map(|(line, text)| parse_instruction(text).map_err(|kind| Error { line, kind }))
+        .collect::<Result<_>>()?;
+
+    Ok(Program::new(instructions, result))
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split(['#', ';']).next().unwrap_or("")
+}
+
+fn parse_header(text: &str) -> result::Result<Value, ErrorKind> {
+    let mut tokens = text.split_whitespace();
+    let result = parse_value(tokens.next())?;
+
+    match tokens.next() {
+        Some("where") => (),
+        Some(token) => return Err(ErrorKind::ExpectedWhere(token.to_string())),
+        None => return Err(ErrorKind::ExpectedWhere(String::new())),
+    }
+
+    expect_eol(tokens.next())?;
+    Ok(result)
+}
+
+fn parse_instruction(text: &str) -> result::Result<Instruction, ErrorKind> {
+    let mut tokens = text.split_whitespace();
+    let name = tokens.next().unwrap_or("");
+    let dst = parse_memory(tokens.next())?;
+
+    let instr = match name {
+        "movabs" => Instruction::MoveAbs(dst, parse_immediate(tokens.next())?),
+        "mov" => Instruction::Move(dst, parse_value(tokens.next())?),
+        "add" => Instruction::Add(dst, parse_value(tokens.next())?),
+        "xor" => Instruction::Xor(dst, parse_value(tokens.next())?),
+        "rotl" => Instruction::RotLeft(dst, parse_value(tokens.next())?),
+        "rotr" => Instruction::RotRight(dst, parse_value(tokens.next())?),
+        _ => return Err(ErrorKind::UnknownInstruction(name.to_string())),
+    };
+
+    expect_eol(tokens.next())?;
+    Ok(instr)
+}
+
+fn parse_memory(token: Option<&str>) -> result::Result<Memory, ErrorKind> {
+    let token = token.ok_or(ErrorKind::ExpectedOperand)?;
+    let num = token
+        .strip_prefix('%')
+        .ok_or_else(|| ErrorKind::ExpectedMemory(token.to_string()))?;
+
+    parse_number(num)
+}
+
+fn parse_immediate<N: FromStr>(token: Option<&str>) -> result::Result<N, ErrorKind> {
+    let token = token.ok_or(ErrorKind::ExpectedOperand)?;
+    let num = token
+        .strip_prefix('$')
+        .ok_or_else(|| ErrorKind::ExpectedValue(token.to_string()))?;
+
+    parse_number(num)
+}
+
+fn parse_value(token: Option<&str>) -> result::Result<Value, ErrorKind> {
+    match token {
+        Some(mem) if mem.starts_with('%') => parse_memory(token).map(Value::Reference),
+        Some(imm) if imm.starts_with('$') => parse_immediate(token).map(Value::Immediate),
+        Some(token) => Err(ErrorKind::ExpectedValue(token.to_string())),
+        None => Err(ErrorKind::ExpectedOperand),
+    }
+}
+
+fn parse_number<N: FromStr>(text: &str) -> result::Result<N, ErrorKind> {
+    text.parse()
+        .map_err(|_| ErrorKind::ExpectedNumber(text.to_string()))
+}
+
+fn expect_eol(token: Option<&str>) -> result::Result<(), ErrorKind> {
+    match token {
+        Some(token) => Err(ErrorKind::ExpectedEol(token.to_string())),
+        None => Ok(()),
+    }
+}
+
+impl FromStr for Program {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Program> {
+        parse(text)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::MissingHeader => write!(f, "expected a `<value> where` header"),
+            ErrorKind::ExpectedWhere(found) => write!(f, "expected `where`, found `{found}`"),
+            ErrorKind::UnknownInstruction(found) => write!(f, "unknown instruction `{found}`"),
+            ErrorKind::ExpectedMemory(found) => {
+                write!(f, "expected a memory slot like `%4`, found `{found}`")
+            }
+            ErrorKind::ExpectedValue(found) => write!(
+                f,
+                "expected a memory slot like `%4` or an immediate like `$17`, found `{found}`"
+            ),
+            ErrorKind::ExpectedNumber(found) => write!(f, "`{found}` is not a number in range"),
+            ErrorKind::ExpectedOperand => write!(f, "expected another operand"),
+            ErrorKind::ExpectedEol(found) => {
+                write!(f, "expected the end of the line, found `{found}`")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::expr::Expr;
+    use crate::ssa::code::Function;
+    use crate::ssa::gen::emit;
+    use rand::prelude::*;
+
+    #[test]
+    fn display_parse_roundtrip() {
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+            let prog = emit(&Function::from(&expr).optimize());
+            let text = prog.to_string();
+            let parsed = parse(&text).unwrap_or_else(|err| panic!("{err}\nin\n{text}"));
+
+            assert_eq!(text, parsed.to_string());
+        }
+    }
+
+    #[test]
+    fn labelled_errors() {
+        let text = "%2 where\nmov %4 %0\n\n# a comment\nadd %4 17\n";
+        let err = parse(text).unwrap_err();
+
+        assert_eq!(err.line, 5);
+        assert!(matches!(err.kind, ErrorKind::ExpectedValue(_)));
+    }
+}
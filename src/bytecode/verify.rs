@@ -0,0 +1,272 @@
+use super::code::{Instruction, Memory, Program, Value};
+use std::fmt;
+use std::result;
+
+/// the hash state is passed in this slot
+pub const STATE: Memory = 0;
+/// the byte being hashed is passed in this slot
+pub const BYTE: Memory = 1;
+/// the result is returned in this slot, the x86 backend also uses it to move values between stack
+/// slots
+pub const RESULT: Memory = 2;
+/// the x86 backend loads rotation amounts into this slot
+pub const SCRATCH: Memory = 3;
+
+/// the largest slot the x86 backend can address, stack slots are addressed by an 8 bit
+/// displacement below the stack pointer
+pub const JIT_MAX_SLOT: Memory = 8 + 16;
+
+/// the backend that a program is going to be run on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    Interpreter,
+    Jit,
+}
+
+#[derive(Debug)]
+pub enum Location {
+    Instruction(usize, Instruction),
+    Result,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub location: Location,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// a slot is read before anything is written to it
+    UseBeforeDef(Memory),
+    /// the hash state or byte slot is overwritten
+    WriteToInput(Memory),
+    /// the rotation scratch slot is read or written
+    ScratchSlot(Memory),
+    /// the result is not returned in the result slot
+    ResultSlot(Value),
+    /// a slot is read after the backend may have overwritten it
+    Clobbered(Memory),
+    /// a slot is past what the backend can address
+    OutOfBounds(Memory),
+    /// an add or xor immediate would be sign extended by the backend
+    ImmediateTooLarge(u32),
+    /// a rotation immediate would be truncated differently by the backend
+    RotationOutOfRange(u32),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Program {
+    /// check that a program only reads slots it has written, follows the reserved slot
+    /// conventions and fits within the limits of the given backend
+    pub fn verify(&self, target: Target) -> Result<()> {
+        let mut written = vec![false; self.biggest_ptr().max(BYTE) + 1];
+        written[STATE] = true;
+        written[BYTE] = true;
+        let mut clobbered = false;
+
+        for (idx, instr) in self.instructions.iter().enumerate() {
+            let error = |kind| Error {
+                location: Location::Instruction(idx, *instr),
+                kind,
+            };
+            let (dst, src) = operands(*instr);
+            let reads = src.into_iter().chain(reads_dst(*instr).then_some(dst));
+
+            for slot in src.into_iter().chain([dst]) {
+                if slot == SCRATCH {
+                    return Err(error(ErrorKind::ScratchSlot(slot)));
+                }
+                if target == Target::Jit && slot > JIT_MAX_SLOT {
+                    return Err(error(ErrorKind::OutOfBounds(slot)));
+                }
+            }
+            if dst == STATE || dst == BYTE {
+                return Err(error(ErrorKind::WriteToInput(dst)));
+            }
+            for slot in reads {
+                if !written.get(slot).copied().unwrap_or(false) {
+                    return Err(error(ErrorKind::UseBeforeDef(slot)));
+                }
+                if slot == RESULT && clobbered {
+                    return Err(error(ErrorKind::Clobbered(slot)));
+                }
+            }
+
+            if target == Target::Jit {
+                check_immediate(*instr).map_err(error)?;
+                clobbered |= clobbers_result(*instr);
+            }
+            clobbered &= dst != RESULT;
+            written[dst] = true;
+        }
+
+        let error = |kind| Error {
+            location: Location::Result,
+            kind,
+        };
+
+        match self.result {
+            Value::Reference(RESULT) if !written.get(RESULT).copied().unwrap_or(false) => {
+                Err(error(ErrorKind::UseBeforeDef(RESULT)))
+            }
+            Value::Reference(RESULT) if clobbered => Err(error(ErrorKind::Clobbered(RESULT))),
+            Value::Reference(RESULT) => Ok(()),
+            val => Err(error(ErrorKind::ResultSlot(val))),
+        }
+    }
+}
+
+/// the slot an instruction writes to, and the slot it reads from (other than its destination)
+fn operands(instr: Instruction) -> (Memory, Option<Memory>) {
+    match instr {
+        Instruction::MoveAbs(dst, _)
+        | Instruction::Move(dst, Value::Immediate(_))
+        | Instruction::Add(dst, Value::Immediate(_))
+        | Instruction::Xor(dst, Value::Immediate(_))
+        | Instruction::RotLeft(dst, Value::Immediate(_))
+        | Instruction::RotRight(dst, Value::Immediate(_)) => (dst, None),
+        Instruction::Move(dst, Value::Reference(src))
+        | Instruction::Add(dst, Value::Reference(src))
+        | Instruction::Xor(dst, Value::Reference(src))
+        | Instruction::RotLeft(dst, Value::Reference(src))
+        | Instruction::RotRight(dst, Value::Reference(src)) => (dst, Some(src)),
+    }
+}
+
+fn reads_dst(instr: Instruction) -> bool {
+    !matches!(instr, Instruction::MoveAbs(..) | Instruction::Move(..))
+}
+
+fn is_stack(slot: Memory) -> bool {
+    slot > 8
+}
+
+/// whether the x86 backend goes through the result register to emit an instruction
+fn clobbers_result(instr: Instruction) -> bool {
+    match instr {
+        Instruction::MoveAbs(dst, _)
+        | Instruction::Move(dst, Value::Immediate(_))
+        | Instruction::RotLeft(dst, _)
+        | Instruction::RotRight(dst, _) => is_stack(dst),
+        Instruction::Move(dst, Value::Reference(src))
+        | Instruction::Add(dst, Value::Reference(src))
+        | Instruction::Xor(dst, Value::Reference(src)) => is_stack(dst) && is_stack(src),
+        Instruction::Add(_, Value::Immediate(_)) | Instruction::Xor(_, Value::Immediate(_)) => {
+            false
+        }
+    }
+}
+
+fn check_immediate(instr: Instruction) -> result::Result<(), ErrorKind> {
+    match instr {
+        Instruction::Add(_, Value::Immediate(num)) | Instruction::Xor(_, Value::Immediate(num))
+            if num > i32::MAX as u32 =>
+        {
+            Err(ErrorKind::ImmediateTooLarge(num))
+        }
+        Instruction::RotLeft(_, Value::Immediate(num))
+        | Instruction::RotRight(_, Value::Immediate(num))
+            if num % u32::from(u8::MAX) % 64 != num % 64 =>
+        {
+            Err(ErrorKind::RotationOutOfRange(num))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UseBeforeDef(slot) => write!(f, "%{slot} is read before it is written"),
+            ErrorKind::WriteToInput(slot) => write!(f, "%{slot} holds an input and is read only"),
+            ErrorKind::ScratchSlot(slot) => {
+                write!(f, "%{slot} is reserved as scratch space for rotations")
+            }
+            ErrorKind::ResultSlot(val) => {
+                write!(f, "the result is {val}, but it has to be %{RESULT}")
+            }
+            ErrorKind::Clobbered(slot) => {
+                write!(f, "%{slot} is overwritten by the backend before it is read")
+            }
+            ErrorKind::OutOfBounds(slot) => {
+                write!(f, "%{slot} is past the last slot %{JIT_MAX_SLOT}")
+            }
+            ErrorKind::ImmediateTooLarge(num) => {
+                write!(f, "${num} would be sign extended, it has to fit in 31 bits")
+            }
+            ErrorKind::RotationOutOfRange(num) => {
+                write!(
+                    f,
+                    "rotating by ${num} would be truncated, it has to be less than 64"
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Location::Instruction(idx, instr) => {
+                write!(f, "instruction {idx} `{instr}`: {}", self.kind)
+            }
+            Location::Result => write!(f, "result: {}", self.kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::expr::Expr;
+    use crate::ssa::code::Function;
+    use crate::ssa::gen::emit;
+    use rand::prelude::*;
+
+    fn verify(text: &str, target: Target) -> Result<()> {
+        text.parse::<Program>().unwrap().verify(target)
+    }
+
+    #[test]
+    fn emitted_programs_verify() {
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+            let prog = emit(&Function::from(&expr).optimize());
+
+            if let Err(err) = prog.verify(Target::Jit) {
+                panic!("{err}\n{expr}\n{prog}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_programs() {
+        let cases = [
+            ("%2 where\nadd %4 %0\nmov %2 %4", Target::Interpreter),
+            ("%2 where\nmov %4 %5\nmov %2 %4", Target::Interpreter),
+            ("%2 where\nxor %0 %1\nmov %2 %0", Target::Interpreter),
+            ("%2 where\nmov %3 %1\nrotl %0 %3", Target::Interpreter),
+            ("%4 where\nmov %4 %0", Target::Interpreter),
+            ("%2 where\nmov %2 %0\nmov %9 $5\nadd %2 %9", Target::Jit),
+            ("%2 where\nmov %2 %0\nmovabs %9 $5", Target::Jit),
+            ("%2 where\nmov %25 %0\nmov %2 %25", Target::Jit),
+            ("%2 where\nmov %2 %0\nadd %2 $4000000000", Target::Jit),
+            ("%2 where\nmov %2 %0\nrotl %2 $300", Target::Jit),
+        ];
+
+        for (text, target) in cases {
+            assert!(verify(text, target).is_err(), "{}", text);
+        }
+
+        assert!(verify("%2 where\nmov %2 %0\nmovabs %9 $5", Target::Interpreter).is_ok());
+        assert!(verify(
+            "%2 where\nmov %2 %0\nadd %2 $4000000000",
+            Target::Interpreter
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,241 @@
+use super::code::{Instruction, Memory, Program, Value};
+use super::verify::{self, Target, BYTE, RESULT, STATE};
+use crate::hash::{BatchHash, Hash};
+use std::cell::RefCell;
+
+/// an instruction decoded ahead of time, so that running it doesn't have to look at the operand
+/// kind or widen immediates
+#[derive(Copy, Clone, Debug)]
+enum Op {
+    Load(Memory, u64),
+    Move(Memory, Memory),
+    AddImm(Memory, u64),
+    AddMem(Memory, Memory),
+    XorImm(Memory, u64),
+    XorMem(Memory, Memory),
+    RotLeftImm(Memory, u32),
+    RotLeftMem(Memory, Memory),
+    RotRightImm(Memory, u32),
+    RotRightMem(Memory, Memory),
+}
+
+/// an interpreter for a verified program that keeps its memory between calls, it is verified and
+/// allocated once in `Vm::new` so build one per program and hash everything through it
+#[derive(Debug)]
+pub struct Vm {
+    ops: Box<[Op]>,
+    mem: RefCell<Box<[u64]>>,
+}
+
+impl From<Instruction> for Op {
+    fn from(instr: Instruction) -> Self {
+        match instr {
+            Instruction::MoveAbs(dst, num) => Op::Load(dst, num),
+            Instruction::Move(dst, Value::Immediate(num)) => Op::Load(dst, u64::from(num)),
+            Instruction::Move(dst, Value::Reference(src)) => Op::Move(dst, src),
+            Instruction::Add(dst, Value::Immediate(num)) => Op::AddImm(dst, u64::from(num)),
+            Instruction::Add(dst, Value::Reference(src)) => Op::AddMem(dst, src),
+            Instruction::Xor(dst, Value::Immediate(num)) => Op::XorImm(dst, u64::from(num)),
+            Instruction::Xor(dst, Value::Reference(src)) => Op::XorMem(dst, src),
+            Instruction::RotLeft(dst, Value::Immediate(num)) => Op::RotLeftImm(dst, num % 64),
+            Instruction::RotLeft(dst, Value::Reference(src)) => Op::RotLeftMem(dst, src),
+            Instruction::RotRight(dst, Value::Immediate(num)) => Op::RotRightImm(dst, num % 64),
+            Instruction::RotRight(dst, Value::Reference(src)) => Op::RotRightMem(dst, src),
+        }
+    }
+}
+
+impl Vm {
+    pub fn new(prog: &Program) -> verify::Result<Vm> {
+        prog.verify(Target::Interpreter)?;
+
+        Ok(Vm {
+            ops: prog.instructions.iter().copied().map(Op::from).collect(),
+            mem: RefCell::new(vec![0; prog.biggest_ptr().max(RESULT) + 1].into_boxed_slice()),
+        })
+    }
+
+    // rotation amounts are truncated on purpose, only the low 6 bits matter
+    #[allow(clippy::cast_possible_truncation)]
+    #[inline]
+    fn step(&self, mem: &mut [u64], hash_state: u64, byte: u8) -> u64 {
+        mem[STATE] = hash_state;
+        mem[BYTE] = u64::from(byte);
+
+        for op in self.ops.iter().copied() {
+            // SAFETY: the program was verified to only read slots that it has written, and the
+            // memory was sized to fit the largest slot written to
+            unsafe {
+                match op {
+                    Op::Load(dst, num) => *mem.get_unchecked_mut(dst) = num,
+                    Op::Move(dst, src) => *mem.get_unchecked_mut(dst) = *mem.get_unchecked(src),
+                    Op::AddImm(dst, num) => {
+                        let dst = mem.get_unchecked_mut(dst);
+                        *dst = dst.wrapping_add(num);
+                    }
+                    Op::AddMem(dst, src) => {
+                        let src = *mem.get_unchecked(src);
+                        let dst = mem.get_unchecked_mut(dst);
+                        *dst = dst.wrapping_add(src);
+                    }
+                    Op::XorImm(dst, num) => *mem.get_unchecked_mut(dst) ^= num,
+                    Op::XorMem(dst, src) => {
+                        let src = *mem.get_unchecked(src);
+                        *mem.get_unchecked_mut(dst) ^= src;
+                    }
+                    Op::RotLeftImm(dst, num) => {
+                        let dst = mem.get_unchecked_mut(dst);
+                        *dst = dst.rotate_left(num);
+                    }
+                    Op::RotLeftMem(dst, src) => {
+                        let src = *mem.get_unchecked(src);
+                        let dst = mem.get_unchecked_mut(dst);
+                        *dst = dst.rotate_left(src as u32);
+                    }
+                    Op::RotRightImm(dst, num) => {
+                        let dst = mem.get_unchecked_mut(dst);
+                        *dst = dst.rotate_right(num);
+                    }
+                    Op::RotRightMem(dst, src) => {
+                        let src = *mem.get_unchecked(src);
+                        let dst = mem.get_unchecked_mut(dst);
+                        *dst = dst.rotate_right(src as u32);
+                    }
+                }
+            }
+        }
+
+        mem[RESULT]
+    }
+}
+
+impl<const N: usize> BatchHash<N> for Vm {
+    // rotation amounts are truncated on purpose, only the low 6 bits matter
+    #[allow(clippy::cast_possible_truncation)]
+    fn hash_batch(&self, init: u64, inputs: &[&[u8]; N]) -> [u64; N] {
+        let len = inputs.first().map_or(0, |bytes| bytes.len());
+        assert!(
+            inputs.iter().all(|bytes| bytes.len() == len),
+            "batched inputs have to be the same length"
+        );
+
+        let mut mem = vec![[0u64; N]; self.mem.borrow().len()];
+        mem[RESULT] = [init; N];
+
+        for idx in 0..len {
+            mem[STATE] = mem[RESULT];
+            mem[BYTE] = inputs.map(|bytes| u64::from(bytes[idx]));
+
+            for op in self.ops.iter().copied() {
+                match op {
+                    Op::Load(dst, num) => mem[dst] = [num; N],
+                    Op::Move(dst, src) => mem[dst] = mem[src],
+                    Op::AddImm(dst, num) => lanes(&mut mem[dst], &[num; N], u64::wrapping_add),
+                    Op::AddMem(dst, src) => {
+                        let src = mem[src];
+                        lanes(&mut mem[dst], &src, u64::wrapping_add);
+                    }
+                    Op::XorImm(dst, num) => lanes(&mut mem[dst], &[num; N], |a, b| a ^ b),
+                    Op::XorMem(dst, src) => {
+                        let src = mem[src];
+                        lanes(&mut mem[dst], &src, |a, b| a ^ b);
+                    }
+                    Op::RotLeftImm(dst, num) => {
+                        lanes(&mut mem[dst], &[0; N], |a, _| a.rotate_left(num));
+                    }
+                    Op::RotLeftMem(dst, src) => {
+                        let src = mem[src];
+                        lanes(&mut mem[dst], &src, |a, b| a.rotate_left(b as u32));
+                    }
+                    Op::RotRightImm(dst, num) => {
+                        lanes(&mut mem[dst], &[0; N], |a, _| a.rotate_right(num));
+                    }
+                    Op::RotRightMem(dst, src) => {
+                        let src = mem[src];
+                        lanes(&mut mem[dst], &src, |a, b| a.rotate_right(b as u32));
+                    }
+                }
+            }
+        }
+
+        mem[RESULT]
+    }
+}
+
+/// apply an operation lane by lane, which the compiler can turn into simd instructions
+#[inline]
+fn lanes<const N: usize>(dst: &mut [u64; N], src: &[u64; N], op: impl Fn(u64, u64) -> u64) {
+    for (dst, src) in dst.iter_mut().zip(src) {
+        *dst = op(*dst, *src);
+    }
+}
+
+impl Hash for Vm {
+    fn hash_bytes(&self, init: u64, bytes: &[u8]) -> u64 {
+        let mut mem = self.mem.borrow_mut();
+        let mut hash = init;
+
+        for byte in bytes {
+            hash = self.step(&mut mem, hash, *byte);
+        }
+
+        hash
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bytecode::gen::emit;
+    use crate::expr::expr::Expr;
+    use crate::ssa::code::Function;
+    use rand::prelude::*;
+
+    const INIT: u64 = 0;
+
+    #[test]
+    fn vm_eval_eq() {
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+
+            for prog in [
+                emit(&expr, 6),
+                crate::ssa::gen::emit(&Function::from(&expr)),
+            ] {
+                let vm = Vm::new(&prog).unwrap();
+
+                for _ in 0..10 {
+                    let hash_state = rng.gen();
+                    let byte = rng.gen();
+
+                    assert_eq!(
+                        vm.hash_bytes(hash_state, &[byte]),
+                        prog.eval(hash_state, byte),
+                        "{expr}\n{prog}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vm_batch_eq() {
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+            let vm = Vm::new(&emit(&expr, 6)).unwrap();
+            let inputs: [Vec<u8>; 4] = [(); 4].map(|()| (0..10).map(|_| rng.gen()).collect());
+            let inputs = [&inputs[0][..], &inputs[1], &inputs[2], &inputs[3]];
+
+            let batch = vm.hash_batch(INIT, &inputs);
+            assert_eq!(
+                batch,
+                inputs.map(|bytes| vm.hash_bytes(INIT, bytes)),
+                "{expr}"
+            );
+        }
+    }
+}
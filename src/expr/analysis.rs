@@ -0,0 +1,248 @@
+use super::expr::{Expr, Tag};
+use super::linear::is_affine;
+use std::fmt;
+
+/// what is known about the value of an expression for every hash state and byte
+///
+/// the approximation is conservative: a bit marked as known always has that value, but a bit
+/// marked as depending on an input may turn out not to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bits {
+    /// the bits that are always zero
+    pub zeros: u64,
+    /// the bits that are always one
+    pub ones: u64,
+    /// the bits that may depend on the hash state
+    pub state: u64,
+    /// the bits that may depend on the byte
+    pub byte: u64,
+    /// whether the hash state may reach the value other than as a rotation amount
+    pub state_operand: bool,
+    /// whether the byte may reach the value other than as a rotation amount
+    pub byte_operand: bool,
+}
+
+/// a reason a tagged expression can't be a good hash
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Degeneracy {
+    /// the result is the same for every byte, so every key of a length collides
+    IgnoresByte,
+    /// the result is the same for every hash state, so it only depends on the last byte
+    IgnoresState,
+    /// the byte only picks a rotation, so at most 6 of its bits matter
+    ByteOnlyRotates,
+    /// the hash state only picks a rotation, so at most 6 of its bits survive each byte
+    StateOnlyRotates,
+    /// the byte only reaches the low 8 bits, which it overwrites without mixing
+    ByteInLowBits,
+    /// only xors and constant rotations are used, so the hash is affine over GF(2), see
+    /// `linear::Linearity`
+    Affine,
+}
+
+impl Bits {
+    fn constant(num: u64) -> Bits {
+        Bits {
+            zeros: !num,
+            ones: num,
+            state: 0,
+            byte: 0,
+            state_operand: false,
+            byte_operand: false,
+        }
+    }
+
+    fn is_uniform(&self) -> bool {
+        self.zeros == u64::MAX || self.ones == u64::MAX
+    }
+
+    /// the rotation amount, if its low 6 bits are known
+    fn rotation(&self) -> Option<u32> {
+        ((self.zeros | self.ones) & 63 == 63).then_some((self.ones & 63) as u32)
+    }
+
+    fn xor(a: Bits, b: Bits) -> Bits {
+        Bits {
+            zeros: (a.zeros & b.zeros) | (a.ones & b.ones),
+            ones: (a.zeros & b.ones) | (a.ones & b.zeros),
+            state: a.state | b.state,
+            byte: a.byte | b.byte,
+            state_operand: a.state_operand || b.state_operand,
+            byte_operand: a.byte_operand || b.byte_operand,
+        }
+    }
+
+    fn add(a: Bits, b: Bits) -> Bits {
+        // the bits of the carry that are known, found by adding the smallest and largest
+        // possible values of the operands
+        let sum_max = (!a.zeros).wrapping_add(!b.zeros);
+        let sum_min = a.ones.wrapping_add(b.ones);
+        let carry_zeros = !(sum_max ^ a.zeros ^ b.zeros);
+        let carry_ones = sum_min ^ a.ones ^ b.ones;
+        let known = (a.zeros | a.ones) & (b.zeros | b.ones) & (carry_zeros | carry_ones);
+
+        Bits {
+            zeros: !sum_max & known,
+            ones: sum_min & known,
+            state: carry_up(a.state | b.state),
+            byte: carry_up(a.byte | b.byte),
+            state_operand: a.state_operand || b.state_operand,
+            byte_operand: a.byte_operand || b.byte_operand,
+        }
+    }
+
+    fn rotate(a: Bits, b: Bits, rotate: fn(u64, u32) -> u64) -> Bits {
+        if a.is_uniform() {
+            return a;
+        }
+
+        match b.rotation() {
+            Some(amount) => Bits {
+                zeros: rotate(a.zeros, amount),
+                ones: rotate(a.ones, amount),
+                state: rotate(a.state, amount),
+                byte: rotate(a.byte, amount),
+                ..a
+            },
+            // any bit of `a` can end up anywhere, and the amount moves every bit
+            None => Bits {
+                zeros: 0,
+                ones: 0,
+                state: spread(a.state | (b.state & 63)),
+                byte: spread(a.byte | (b.byte & 63)),
+                ..a
+            },
+        }
+    }
+}
+
+/// a carry out of a bit can change every bit above it
+fn carry_up(mask: u64) -> u64 {
+    if mask == 0 {
+        0
+    } else {
+        u64::MAX << mask.trailing_zeros()
+    }
+}
+
+fn spread(mask: u64) -> u64 {
+    if mask == 0 {
+        0
+    } else {
+        u64::MAX
+    }
+}
+
+/// compute what is known about every bit of an expression
+pub fn bits(expr: &Expr<Tag>) -> Bits {
+    match expr {
+        Expr::Add(a, b) => Bits::add(bits(a), bits(b)),
+        Expr::Xor(a, b) => Bits::xor(bits(a), bits(b)),
+        Expr::RotLeft(a, b) => Bits::rotate(bits(a), bits(b), u64::rotate_left),
+        Expr::RotRight(a, b) => Bits::rotate(bits(a), bits(b), u64::rotate_right),
+        Expr::Tag(Tag::Const(num)) => Bits::constant(*num),
+        Expr::Tag(Tag::HashState) => Bits {
+            zeros: 0,
+            ones: 0,
+            state: u64::MAX,
+            byte: 0,
+            state_operand: true,
+            byte_operand: false,
+        },
+        Expr::Tag(Tag::Byte) => Bits {
+            zeros: !0xff,
+            ones: 0,
+            state: 0,
+            byte: 0xff,
+            state_operand: false,
+            byte_operand: true,
+        },
+    }
+}
+
+/// find a reason a tagged expression can't be a good hash, if there is one
+pub fn degeneracy(expr: &Expr<Tag>) -> Option<Degeneracy> {
+    let bits = bits(expr);
+
+    if bits.byte == 0 {
+        Some(Degeneracy::IgnoresByte)
+    } else if bits.state == 0 {
+        Some(Degeneracy::IgnoresState)
+    } else if !bits.byte_operand {
+        Some(Degeneracy::ByteOnlyRotates)
+    } else if !bits.state_operand {
+        Some(Degeneracy::StateOnlyRotates)
+    } else if bits.byte & !0xff == 0 {
+        Some(Degeneracy::ByteInLowBits)
+    } else if is_affine(expr) {
+        Some(Degeneracy::Affine)
+    } else {
+        None
+    }
+}
+
+impl fmt::Display for Degeneracy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Degeneracy::IgnoresByte => write!(f, "ignores the byte"),
+            Degeneracy::IgnoresState => write!(f, "ignores the hash state"),
+            Degeneracy::ByteOnlyRotates => write!(f, "only rotates by the byte"),
+            Degeneracy::StateOnlyRotates => write!(f, "only rotates by the hash state"),
+            Degeneracy::ByteInLowBits => write!(f, "only mixes the byte into the low 8 bits"),
+            Degeneracy::Affine => write!(f, "is affine over GF(2), so easy to invert and collide"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::parse;
+    use crate::hash::Hash;
+    use rand::prelude::*;
+
+    #[test]
+    fn bits_are_sound() {
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+            let bits = bits(&expr);
+
+            for _ in 0..100 {
+                let (state, byte) = (rng.gen(), rng.gen());
+                let hash = expr.hash_bytes(state, &[byte]);
+
+                assert_eq!(hash & bits.zeros, 0, "{}", expr);
+                assert_eq!(hash & bits.ones, bits.ones, "{}", expr);
+
+                let other_state = expr.hash_bytes(rng.gen(), &[byte]);
+                let other_byte = expr.hash_bytes(state, &[rng.gen()]);
+                assert_eq!((hash ^ other_state) & !bits.state, 0, "{}", expr);
+                assert_eq!((hash ^ other_byte) & !bits.byte, 0, "{}", expr);
+            }
+        }
+    }
+
+    #[test]
+    fn finds_degenerate_expressions() {
+        let cases = [
+            ("(state + 17)", Some(Degeneracy::IgnoresByte)),
+            ("((state xor byte) + (byte xor 3))", None),
+            ("((byte + 5) << 12)", Some(Degeneracy::IgnoresState)),
+            ("(state << byte)", Some(Degeneracy::ByteOnlyRotates)),
+            (
+                "(byte + (12345 << state))",
+                Some(Degeneracy::StateOnlyRotates),
+            ),
+            ("((state << 5) xor byte)", Some(Degeneracy::ByteInLowBits)),
+            ("((state xor byte) << 8)", Some(Degeneracy::Affine)),
+            ("((state xor byte) + (state << 8))", None),
+            ("(((state xor byte) + 1234) << 31)", None),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(degeneracy(&parse(text).unwrap()), expected, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use super::analysis::bits;
+use super::expr::{Expr, Tag};
+use super::linear::eliminate;
+use crate::hash::Hash;
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// whether the state update `state -> f(state, byte)` of an expression is a permutation of the
+/// state for every byte
+#[derive(Clone, Debug, PartialEq)]
+pub enum Bijectivity {
+    /// provably a permutation
+    Bijective,
+    /// not a permutation, proven unless the evidence is an estimate
+    LikelyNotBijective(Evidence),
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Evidence {
+    /// the hash state only reaches `bits` of the output bits
+    NarrowImage { bits: u32 },
+    /// the state only goes through xors and constant rotations, and the matrix of that linear
+    /// map is singular
+    Singular { rank: usize },
+    /// two states give the same result for a byte
+    Collision { states: (u64, u64), byte: u8 },
+    /// random states collided on the low 32 bits of the result far more often than they would for
+    /// a permutation
+    Estimate { collisions: usize, expected: f64 },
+}
+
+/// classify an expression using only structural rules, this is cheap enough to use as a filter
+pub fn classify(expr: &Expr<Tag>) -> Bijectivity {
+    let state_bits = bits(expr).state.count_ones();
+    if state_bits < 64 {
+        return Bijectivity::LikelyNotBijective(Evidence::NarrowImage { bits: state_bits });
+    }
+
+    match structural(expr) {
+        // a result that doesn't depend on the state is caught by the image check above
+        Class::Free | Class::Unknown => Bijectivity::Unknown,
+        Class::Known(bijectivity) => bijectivity,
+    }
+}
+
+/// classify an expression with the structural rules, falling back on hashing `samples` states
+/// with a few bytes and looking for collisions
+pub fn classify_sampled<R: Rng>(expr: &Expr<Tag>, samples: usize, rng: &mut R) -> Bijectivity {
+    match classify(expr) {
+        Bijectivity::Unknown => sample(expr, samples, rng),
+        bijectivity => bijectivity,
+    }
+}
+
+enum Class {
+    /// doesn't depend on the state
+    Free,
+    Known(Bijectivity),
+    Unknown,
+}
+
+fn structural(expr: &Expr<Tag>) -> Class {
+    match expr {
+        Expr::Tag(Tag::HashState) => Class::Known(Bijectivity::Bijective),
+        Expr::Tag(_) => Class::Free,
+        // adding, xoring or rotating by something that doesn't depend on the state is a
+        // permutation, so it keeps whether the other operand is one
+        Expr::Add(a, b) | Expr::Xor(a, b) => match (structural(a), structural(b)) {
+            (Class::Free, Class::Free) => Class::Free,
+            (Class::Free, class) | (class, Class::Free) => class,
+            _ => linear(expr),
+        },
+        Expr::RotLeft(a, b) | Expr::RotRight(a, b) => match structural(b) {
+            Class::Free => structural(a),
+            _ => Class::Unknown,
+        },
+    }
+}
+
+/// decide a state update that is linear in the state by the rank of its matrix
+fn linear(expr: &Expr<Tag>) -> Class {
+    if !is_state_linear(expr) {
+        return Class::Unknown;
+    }
+
+    // the rotations are by constants, so the matrix is the same for every byte
+    let offset = expr.hash_bytes(0, &[0]);
+    let columns: Vec<_> = (0..64)
+        .map(|bit| expr.hash_bytes(1 << bit, &[0]) ^ offset)
+        .collect();
+
+    match eliminate(&columns).0 {
+        64 => Class::Known(Bijectivity::Bijective),
+        rank => Class::Known(Bijectivity::LikelyNotBijective(Evidence::Singular { rank })),
+    }
+}
+
+/// whether an expression is `matrix * state ^ offset(byte)`, with a matrix that doesn't depend
+/// on the byte
+pub fn is_state_linear(expr: &Expr<Tag>) -> bool {
+    match expr {
+        _ if !uses_state(expr) => true,
+        Expr::Xor(a, b) => is_state_linear(a) && is_state_linear(b),
+        Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+            is_state_linear(a) && bits(b).byte == 0 && bits(b).state == 0
+        }
+        Expr::Add(..) => false,
+        Expr::Tag(_) => true,
+    }
+}
+
+/// whether the hash state is a leaf of an expression
+pub fn uses_state(expr: &Expr<Tag>) -> bool {
+    match expr {
+        Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+            uses_state(a) || uses_state(b)
+        }
+        Expr::Tag(tag) => matches!(tag, Tag::HashState),
+    }
+}
+
+/// hash low entropy states (zero, single bits and small numbers) and random states, with a few
+/// bytes, looking for two states that collide
+fn sample<R: Rng>(expr: &Expr<Tag>, samples: usize, rng: &mut R) -> Bijectivity {
+    let low_entropy = (0..64)
+        .map(|bit| 1u64 << bit)
+        .chain((0..64).map(|bit| !(1u64 << bit)))
+        .chain(0..256);
+    let mut low_collisions = 0;
+    let mut random = 0;
+
+    for byte in [0, 1, 0x80, 0xff, rng.gen()] {
+        let mut seen = HashMap::new();
+        let mut low_bits = HashMap::new();
+
+        let states = low_entropy
+            .clone()
+            .map(|state| (state, false))
+            .chain((0..samples).map(|_| (rng.gen(), true)));
+        for (state, is_random) in states {
+            let hash = expr.hash_bytes(state, &[byte]);
+
+            match seen.insert(hash, state) {
+                Some(other) if other != state => {
+                    return Bijectivity::LikelyNotBijective(Evidence::Collision {
+                        states: (other, state),
+                        byte,
+                    });
+                }
+                _ => (),
+            }
+            if is_random {
+                random += 1;
+                *low_bits.entry(hash as u32).or_insert(0) += 1;
+            }
+        }
+        low_collisions += low_bits.values().map(|n| n * (n - 1) / 2).sum::<usize>();
+    }
+
+    // the number of pairs of random states expected to collide on 32 bits, over all the bytes
+    let expected = (samples * samples.saturating_sub(1)) as f64 / 2. / (1u64 << 32) as f64
+        * (random / samples.max(1)) as f64;
+    if low_collisions as f64 > 4. * expected + 8. {
+        Bijectivity::LikelyNotBijective(Evidence::Estimate {
+            collisions: low_collisions,
+            expected,
+        })
+    } else {
+        Bijectivity::Unknown
+    }
+}
+
+impl fmt::Display for Bijectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bijectivity::Bijective => write!(f, "a permutation of the state for every byte"),
+            Bijectivity::LikelyNotBijective(evidence @ Evidence::Estimate { .. }) => {
+                write!(f, "likely not a permutation of the state, {}", evidence)
+            }
+            Bijectivity::LikelyNotBijective(evidence) => {
+                write!(f, "not a permutation of the state, {}", evidence)
+            }
+            Bijectivity::Unknown => write!(f, "not known to be a permutation of the state"),
+        }
+    }
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Evidence::NarrowImage { bits } => {
+                write!(f, "the state only reaches {} of the 64 output bits", bits)
+            }
+            Evidence::Singular { rank } => {
+                write!(f, "the state is mapped linearly with rank {} of 64", rank)
+            }
+            Evidence::Collision {
+                states: (a, b),
+                byte,
+            } => write!(f, "states {:#x} and {:#x} collide with byte {}", a, b, byte),
+            Evidence::Estimate {
+                collisions,
+                expected,
+            } => write!(
+                f,
+                "{} collisions on the low 32 bits where {:.2} were expected",
+                collisions, expected
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::parse;
+    use std::collections::HashSet;
+
+    #[test]
+    fn structural_rules() {
+        let cases = [
+            ("(state + byte)", Bijectivity::Bijective),
+            (
+                "(((state xor byte) << 7) + (byte >> 3))",
+                Bijectivity::Bijective,
+            ),
+            ("((state >> byte) xor 12345)", Bijectivity::Bijective),
+            (
+                "((state xor (state << 5)) xor (state >> 11))",
+                Bijectivity::Bijective,
+            ),
+            (
+                "(state xor (state << 1))",
+                Bijectivity::LikelyNotBijective(Evidence::Singular { rank: 63 }),
+            ),
+            ("((state + byte) xor (state << 13))", Bijectivity::Unknown),
+            ("((state xor byte) >> (state + 3))", Bijectivity::Unknown),
+            (
+                "((byte + 7) xor 99)",
+                Bijectivity::LikelyNotBijective(Evidence::NarrowImage { bits: 0 }),
+            ),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(classify(&parse(text).unwrap()), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn sampling_finds_collisions() {
+        let mut rng = thread_rng();
+
+        // doubling the state loses its top bit
+        let expr = parse("(state + state)").unwrap();
+        assert_eq!(classify(&expr), Bijectivity::Unknown);
+        assert!(matches!(
+            classify_sampled(&expr, 1000, &mut rng),
+            Bijectivity::LikelyNotBijective(Evidence::Collision { .. })
+        ));
+    }
+
+    #[test]
+    fn bijective_never_collides() {
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+            if classify(&expr) != Bijectivity::Bijective {
+                continue;
+            }
+
+            let byte = rng.gen();
+            let hashes: HashSet<_> = (0..1000)
+                .map(|state| expr.hash_bytes(state, &[byte]))
+                .collect();
+            assert_eq!(hashes.len(), 1000, "{}", expr);
+        }
+    }
+}
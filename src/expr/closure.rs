@@ -1,66 +1,86 @@
 use super::expr::{Expr, Tag};
-use crate::hash::Hash;
+use crate::hash::{BatchHash, Hash, LANES};
 use std::rc::Rc;
 
+type Closure<'a> = Rc<dyn Fn(u64, u8) -> u64 + 'a>;
+type BatchClosure<'a> = Rc<dyn Fn(&[u64; LANES], &[u64; LANES]) -> [u64; LANES] + 'a>;
+
+/// an expression compiled to closures, one hashing a byte at a time and one hashing `LANES`
+/// inputs at once, one per lane
 #[derive(Clone)]
 pub struct Hasher<'a> {
-    closure: Rc<dyn Fn(u64, u8) -> u64 + 'a>,
+    closure: Closure<'a>,
+    batch: BatchClosure<'a>,
 }
+
 impl<'a> From<&'a Expr<Tag>> for Hasher<'a> {
     fn from(expr: &'a Expr<Tag>) -> Self {
-        match expr {
-            Expr::Add(a, b) => {
-                let ac = Hasher::from(a.as_ref());
-                let bc = Hasher::from(b.as_ref());
-
-                Hasher {
-                    closure: Rc::new(move |hash_state, byte| {
-                        (ac.closure)(hash_state, byte).wrapping_add((bc.closure)(hash_state, byte))
-                    }),
-                }
-            }
-            Expr::Xor(a, b) => {
-                let ac = Hasher::from(a.as_ref());
-                let bc = Hasher::from(b.as_ref());
-
-                Hasher {
-                    closure: Rc::new(move |hash_state, byte| {
-                        (ac.closure)(hash_state, byte) ^ (bc.closure)(hash_state, byte)
-                    }),
-                }
-            }
-            Expr::RotLeft(a, b) => {
-                let ac = Hasher::from(a.as_ref());
-                let bc = Hasher::from(b.as_ref());
-
-                Hasher {
-                    closure: Rc::new(move |hash_state, byte| {
-                        (ac.closure)(hash_state, byte)
-                            .rotate_left((bc.closure)(hash_state, byte) as u32)
-                    }),
-                }
-            }
-            Expr::RotRight(a, b) => {
-                let ac = Hasher::from(a.as_ref());
-                let bc = Hasher::from(b.as_ref());
-
-                Hasher {
-                    closure: Rc::new(move |hash_state, byte| {
-                        (ac.closure)(hash_state, byte)
-                            .rotate_right((bc.closure)(hash_state, byte) as u32)
-                    }),
-                }
-            }
-            Expr::Tag(Tag::Const(num)) => Hasher {
-                closure: Rc::new(|_, _| *num),
-            },
-            Expr::Tag(Tag::HashState) => Hasher {
-                closure: Rc::new(|state, _| state),
-            },
-            Expr::Tag(Tag::Byte) => Hasher {
-                closure: Rc::new(|_, byte| byte as u64),
-            },
+        Hasher {
+            closure: closure(expr),
+            batch: batch(expr),
+        }
+    }
+}
+
+fn closure(expr: &Expr<Tag>) -> Closure<'_> {
+    match expr {
+        Expr::Add(a, b) => {
+            let ac = closure(a);
+            let bc = closure(b);
+
+            Rc::new(move |hash_state, byte| ac(hash_state, byte).wrapping_add(bc(hash_state, byte)))
+        }
+        Expr::Xor(a, b) => {
+            let ac = closure(a);
+            let bc = closure(b);
+
+            Rc::new(move |hash_state, byte| ac(hash_state, byte) ^ bc(hash_state, byte))
+        }
+        Expr::RotLeft(a, b) => {
+            let ac = closure(a);
+            let bc = closure(b);
+
+            Rc::new(move |hash_state, byte| {
+                ac(hash_state, byte).rotate_left(bc(hash_state, byte) as u32)
+            })
+        }
+        Expr::RotRight(a, b) => {
+            let ac = closure(a);
+            let bc = closure(b);
+
+            Rc::new(move |hash_state, byte| {
+                ac(hash_state, byte).rotate_right(bc(hash_state, byte) as u32)
+            })
         }
+        Expr::Tag(Tag::Const(num)) => Rc::new(|_, _| *num),
+        Expr::Tag(Tag::HashState) => Rc::new(|state, _| state),
+        Expr::Tag(Tag::Byte) => Rc::new(|_, byte| byte as u64),
+    }
+}
+
+fn batch(expr: &Expr<Tag>) -> BatchClosure<'_> {
+    let binary = |a, b, op: fn(u64, u64) -> u64| -> BatchClosure<'_> {
+        let (ac, bc) = (batch(a), batch(b));
+
+        Rc::new(move |hash_state, bytes| {
+            let mut a = ac(hash_state, bytes);
+            let b = bc(hash_state, bytes);
+
+            for (a, b) in a.iter_mut().zip(b) {
+                *a = op(*a, b);
+            }
+            a
+        })
+    };
+
+    match expr {
+        Expr::Add(a, b) => binary(a, b, u64::wrapping_add),
+        Expr::Xor(a, b) => binary(a, b, |a, b| a ^ b),
+        Expr::RotLeft(a, b) => binary(a, b, |a, b| a.rotate_left(b as u32)),
+        Expr::RotRight(a, b) => binary(a, b, |a, b| a.rotate_right(b as u32)),
+        Expr::Tag(Tag::Const(num)) => Rc::new(|_, _| [*num; LANES]),
+        Expr::Tag(Tag::HashState) => Rc::new(|state, _| *state),
+        Expr::Tag(Tag::Byte) => Rc::new(|_, bytes| *bytes),
     }
 }
 
@@ -76,6 +96,24 @@ impl Hash for Hasher<'_> {
     }
 }
 
+impl BatchHash<LANES> for Hasher<'_> {
+    fn hash_batch(&self, init: u64, inputs: &[&[u8]; LANES]) -> [u64; LANES] {
+        let len = inputs.first().map_or(0, |bytes| bytes.len());
+        assert!(
+            inputs.iter().all(|bytes| bytes.len() == len),
+            "batched inputs have to be the same length"
+        );
+
+        let mut hash = [init; LANES];
+
+        for idx in 0..len {
+            hash = (self.batch)(&hash, &inputs.map(|bytes| bytes[idx] as u64));
+        }
+
+        hash
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -126,4 +164,24 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn batch_closure_eq() {
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+            let hasher = Hasher::from(&expr);
+            let inputs: [Vec<u8>; LANES] =
+                [(); LANES].map(|_| (0..10).map(|_| rng.gen()).collect());
+            let inputs = inputs.each_ref().map(Vec::as_slice);
+
+            assert_eq!(
+                hasher.hash_batch(INIT, &inputs),
+                inputs.map(|bytes| expr.hash_bytes(INIT, bytes)),
+                "{}",
+                expr
+            );
+        }
+    }
 }
@@ -77,11 +77,11 @@ impl Hash for Expr<Tag> {
 }
 
 impl Expr<Tag> {
-    pub fn rand(rng: &mut ThreadRng) -> Expr<Tag> {
+    pub fn rand<R: Rng>(rng: &mut R) -> Expr<Tag> {
         Expr::rand_with_depth(rng, 0)
     }
 
-    pub fn rand_with_depth(rng: &mut ThreadRng, depth: usize) -> Expr<Tag> {
+    pub fn rand_with_depth<R: Rng>(rng: &mut R, depth: usize) -> Expr<Tag> {
         let seed = rng.gen::<u8>() % if depth >= 10 { 4 } else { 8 };
 
         match seed {
@@ -115,6 +115,8 @@ impl Expr<Tag> {
         }
     }
 
+    // only the direct emitter in `bytecode::gen` needs it, and that is kept for tests
+    #[cfg(test)]
     pub fn depth(&self) -> usize {
         match self {
             Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) | Expr::Add(a, b) => {
@@ -127,6 +129,18 @@ impl Expr<Tag> {
 }
 
 impl<TAG> Expr<TAG> {
+    /// the expression with its tags dropped
+    pub fn shape(&self) -> Expr<()> {
+        let (a, b, op) = match self {
+            Expr::Add(a, b) => (a, b, Expr::Add as fn(_, _) -> _),
+            Expr::Xor(a, b) => (a, b, Expr::Xor as fn(_, _) -> _),
+            Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as fn(_, _) -> _),
+            Expr::RotRight(a, b) => (a, b, Expr::RotRight as fn(_, _) -> _),
+            Expr::Tag(_) => return Expr::Tag(()),
+        };
+        op(Box::new(a.shape()), Box::new(b.shape()))
+    }
+
     pub fn len(&self) -> usize {
         match self {
             Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) | Expr::Add(a, b) => {
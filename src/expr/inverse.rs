@@ -0,0 +1,236 @@
+use super::bijective::{is_state_linear, uses_state};
+use super::expr::{Expr, Operator, Tag};
+use super::linear::has_inputs;
+use crate::hash::Hash;
+use crate::ssa::code::Function;
+use crate::ssa::gen::emit;
+use rand::prelude::*;
+
+/// derive the inverse of a state update that `bijective::classify` proves is a permutation, in
+/// the inverse `state` stands for the result of the update and `byte` for the same byte
+///
+/// operands that don't depend on the state are undone one step at a time, and a part that is
+/// only xors and constant rotations of the state is undone by inverting its matrix
+pub fn invert(expr: &Expr<Tag>) -> Option<Expr<Tag>> {
+    undo(expr, Expr::Tag(Tag::HashState))
+}
+
+/// an expression for the state given `out`, an expression for the value of `expr`
+fn undo(expr: &Expr<Tag>, out: Expr<Tag>) -> Option<Expr<Tag>> {
+    let (a, b) = match expr {
+        Expr::Tag(Tag::HashState) => return Some(out),
+        Expr::Tag(_) => return None,
+        Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => (a, b),
+    };
+
+    match (expr, uses_state(a), uses_state(b)) {
+        (Expr::Add(..), true, false) => undo(a, sub(out, b)),
+        (Expr::Add(..), false, true) => undo(b, sub(out, a)),
+        (Expr::Xor(..), true, false) => undo(a, xor(out, folded(b))),
+        (Expr::Xor(..), false, true) => undo(b, xor(out, folded(a))),
+        (Expr::Xor(..), true, true) => undo_linear(expr, out),
+        (Expr::RotLeft(..), true, false) => undo(a, Expr::RotRight(Box::new(out), b.clone())),
+        (Expr::RotRight(..), true, false) => undo(a, Expr::RotLeft(Box::new(out), b.clone())),
+        _ => None,
+    }
+}
+
+/// undo `matrix * state ^ offset(byte)`, the matrix is built from rotations so it is circulant
+/// and so is its inverse, which makes the inverse a xor of rotations
+fn undo_linear(expr: &Expr<Tag>, out: Expr<Tag>) -> Option<Expr<Tag>> {
+    if !is_state_linear(expr) {
+        return None;
+    }
+
+    let offset = with_state(expr, &Expr::Tag(Tag::Const(0)));
+    let polynomial = expr.hash_bytes(1, &[0]) ^ expr.hash_bytes(0, &[0]);
+    let inverse = invert_polynomial(polynomial)?;
+
+    let unmixed = xor(out, offset);
+    (0..64)
+        .filter(|k| inverse >> k & 1 == 1)
+        .map(|k| match k {
+            0 => unmixed.clone(),
+            k => Expr::RotLeft(
+                Box::new(unmixed.clone()),
+                Box::new(Expr::Tag(Tag::Const(k))),
+            ),
+        })
+        .reduce(|acc, term| Expr::Xor(Box::new(acc), Box::new(term)))
+}
+
+/// multiply two polynomials modulo `x^64 - 1` over GF(2), which is composing the xors of
+/// rotations they stand for
+fn multiply(a: u64, b: u64) -> u64 {
+    (0..64)
+        .filter(|k| a >> k & 1 == 1)
+        .fold(0, |acc, k| acc ^ b.rotate_left(k))
+}
+
+/// the units modulo `x^64 - 1 = (x + 1)^64` form a group of order `2^63`, so the inverse of `p`
+/// is `p^(2^63 - 1)`, the product of `p^(2^k)` for `k` below 63
+fn invert_polynomial(polynomial: u64) -> Option<u64> {
+    let (mut inverse, mut square) = (1, polynomial);
+    for _ in 0..63 {
+        inverse = multiply(inverse, square);
+        square = multiply(square, square);
+    }
+
+    (multiply(inverse, polynomial) == 1).then_some(inverse)
+}
+
+/// `out - num` as `out + (!num + 1)`, folded when `num` is a constant
+fn sub(out: Expr<Tag>, num: &Expr<Tag>) -> Expr<Tag> {
+    let negated = match constant(num) {
+        Some(num) => Expr::Tag(Tag::Const(num.wrapping_neg())),
+        None => Expr::Add(
+            Box::new(Expr::Xor(
+                Box::new(num.clone()),
+                Box::new(Expr::Tag(Tag::Const(u64::MAX))),
+            )),
+            Box::new(Expr::Tag(Tag::Const(1))),
+        ),
+    };
+    Expr::Add(Box::new(out), Box::new(negated))
+}
+
+fn xor(out: Expr<Tag>, num: Expr<Tag>) -> Expr<Tag> {
+    match constant(&num) {
+        Some(0) => out,
+        _ => Expr::Xor(Box::new(out), Box::new(num)),
+    }
+}
+
+/// an operand that doesn't depend on the state, folded if it doesn't depend on the byte either
+fn folded(num: &Expr<Tag>) -> Expr<Tag> {
+    constant(num).map_or_else(|| num.clone(), |num| Expr::Tag(Tag::Const(num)))
+}
+
+/// the value of an expression that doesn't depend on the state or the byte
+fn constant(expr: &Expr<Tag>) -> Option<u64> {
+    (!has_inputs(expr)).then(|| expr.hash_bytes(0, &[0]))
+}
+
+/// replace every `state` leaf of an expression, folding the parts that become constant and
+/// dropping the operands that become the zero of their operator
+fn with_state(expr: &Expr<Tag>, state: &Expr<Tag>) -> Expr<Tag> {
+    let (a, b, op) = match expr {
+        Expr::Add(a, b) => (a, b, Expr::Add as Operator),
+        Expr::Xor(a, b) => (a, b, Expr::Xor as Operator),
+        Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator),
+        Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator),
+        Expr::Tag(Tag::HashState) => return state.clone(),
+        Expr::Tag(tag) => return Expr::Tag(tag.clone()),
+    };
+    let (a, b) = (with_state(a, state), with_state(b, state));
+
+    match (expr, constant(&a), constant(&b)) {
+        (_, Some(_), Some(_)) => folded(&op(Box::new(a), Box::new(b))),
+        (Expr::Add(..) | Expr::Xor(..), Some(0), _) => b,
+        (_, _, Some(0)) => a,
+        _ => op(Box::new(a), Box::new(b)),
+    }
+}
+
+/// check that the inverse undoes the update on `samples` random states and bytes, running both
+/// as programs with `Program::eval`, and return a state and byte it fails on otherwise
+pub fn round_trip<R: Rng>(
+    expr: &Expr<Tag>,
+    inverse: &Expr<Tag>,
+    samples: usize,
+    rng: &mut R,
+) -> Result<(), (u64, u8)> {
+    let forward = emit(&Function::from(expr).optimize());
+    let backward = emit(&Function::from(inverse).optimize());
+
+    for _ in 0..samples {
+        let (state, byte) = (rng.gen(), rng.gen());
+        if backward.eval(forward.eval(state, byte), byte) != state {
+            return Err((state, byte));
+        }
+    }
+    Ok(())
+}
+
+/// the initial state that hashes `bytes` to `hash`, found by running the inverse over the bytes
+/// backwards
+///
+/// this recovers the seed of a hash from a single known key and its hash, and gives any number
+/// of inputs colliding on a chosen hash, one for each key
+pub fn unhash(inverse: &Expr<Tag>, hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(hash, |state, byte| inverse.hash_bytes(state, &[*byte]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::bijective::{classify, Bijectivity};
+    use crate::expr::parse::parse;
+
+    #[test]
+    fn inverts_bijective_expressions() {
+        let mut rng = thread_rng();
+
+        for _ in 0..2000 {
+            let expr = Expr::rand(&mut rng);
+            if classify(&expr) != Bijectivity::Bijective {
+                continue;
+            }
+
+            let inverse = invert(&expr).unwrap_or_else(|| panic!("no inverse for {}", expr));
+            assert_eq!(
+                round_trip(&expr, &inverse, 100, &mut rng),
+                Ok(()),
+                "{} inverted to {}",
+                expr,
+                inverse
+            );
+        }
+    }
+
+    #[test]
+    fn inverts_arx_steps() {
+        let mut rng = thread_rng();
+
+        for text in [
+            "(((state xor byte) + 1234567) << 31)",
+            "((state >> byte) xor (byte + 99))",
+            "(((state xor (state << 5)) xor (state >> 11)) + byte)",
+            "((state xor (state << 7)) xor ((state >> 3) xor byte))",
+        ] {
+            let expr = parse(text).unwrap();
+            let inverse = invert(&expr).unwrap();
+            assert_eq!(
+                round_trip(&expr, &inverse, 1000, &mut rng),
+                Ok(()),
+                "{}",
+                text
+            );
+        }
+
+        for text in [
+            "(state xor (state << 1))",
+            "((state + byte) xor (state << 13))",
+        ] {
+            assert!(invert(&parse(text).unwrap()).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn unhash_recovers_init() {
+        let mut rng = thread_rng();
+        let expr = parse("((((state xor byte) + 1234567) << 31) xor 77)").unwrap();
+        let inverse = invert(&expr).unwrap();
+
+        for len in 0..20 {
+            let (init, bytes): (u64, Vec<u8>) = (rng.gen(), (0..len).map(|_| rng.gen()).collect());
+            assert_eq!(
+                unhash(&inverse, expr.hash_bytes(init, &bytes), &bytes),
+                init
+            );
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use super::expr::{Expr, Tag};
+use crate::hash::Hash;
+use std::fmt;
+
+/// the number of input bits, the 64 bits of the hash state followed by the 8 bits of the byte
+pub const INPUTS: usize = 64 + 8;
+
+/// an expression that is affine over GF(2), `f(state, byte) = matrix * (state, byte) ^ constant`
+pub struct Linearity {
+    /// the image of every input bit, indexed by input bit
+    pub columns: [u64; INPUTS],
+    pub constant: u64,
+    /// the rank of the whole matrix
+    pub rank: usize,
+    /// the rank of the state columns, 64 if the state update is invertible for a fixed byte
+    pub state_rank: usize,
+    /// a basis of the input differences that don't change the result, as masks with the state
+    /// in the low 64 bits and the byte in the next 8
+    pub kernel: Vec<u128>,
+}
+
+/// whether an expression only uses xors and rotations by constants, so is affine over GF(2)
+pub fn is_affine(expr: &Expr<Tag>) -> bool {
+    match expr {
+        _ if !has_inputs(expr) => true,
+        Expr::Xor(a, b) => is_affine(a) && is_affine(b),
+        Expr::RotLeft(a, b) | Expr::RotRight(a, b) => is_affine(a) && !has_inputs(b),
+        Expr::Add(a, b) => {
+            (!has_inputs(a) && eval(a) == 0 && is_affine(b))
+                || (!has_inputs(b) && eval(b) == 0 && is_affine(a))
+        }
+        Expr::Tag(_) => true,
+    }
+}
+
+/// the largest subexpressions that are affine and depend on an input, which are the whole
+/// expression if it is affine
+pub fn affine_parts(expr: &Expr<Tag>) -> Vec<&Expr<Tag>> {
+    let mut parts = Vec::new();
+    collect_affine_parts(expr, &mut parts);
+    parts
+}
+
+fn collect_affine_parts<'e>(expr: &'e Expr<Tag>, parts: &mut Vec<&'e Expr<Tag>>) {
+    match expr {
+        _ if has_inputs(expr) && is_affine(expr) => parts.push(expr),
+        Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+            collect_affine_parts(a, parts);
+            collect_affine_parts(b, parts);
+        }
+        Expr::Tag(_) => (),
+    }
+}
+
+/// whether the hash state or the byte is a leaf of an expression
+pub fn has_inputs(expr: &Expr<Tag>) -> bool {
+    match expr {
+        Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+            has_inputs(a) || has_inputs(b)
+        }
+        Expr::Tag(Tag::Const(_)) => false,
+        Expr::Tag(Tag::HashState | Tag::Byte) => true,
+    }
+}
+
+fn eval(expr: &Expr<Tag>) -> u64 {
+    expr.hash_bytes(0, &[0])
+}
+
+impl Linearity {
+    /// build the matrix of an affine expression, or `None` if it isn't affine
+    pub fn of(expr: &Expr<Tag>) -> Option<Linearity> {
+        if !is_affine(expr) {
+            return None;
+        }
+
+        let constant = eval(expr);
+        let mut columns = [0; INPUTS];
+        for (bit, column) in columns.iter_mut().enumerate() {
+            *column = if bit < 64 {
+                expr.hash_bytes(1 << bit, &[0])
+            } else {
+                expr.hash_bytes(0, &[1 << (bit - 64)])
+            } ^ constant;
+        }
+
+        let (rank, kernel) = eliminate(&columns);
+        let (state_rank, _) = eliminate(&columns[..64]);
+
+        Some(Linearity {
+            columns,
+            constant,
+            rank,
+            state_rank,
+            kernel,
+        })
+    }
+
+    /// apply the affine map to an input
+    #[cfg(test)]
+    pub fn apply(&self, state: u64, byte: u8) -> u64 {
+        let input = u128::from(state) | u128::from(byte) << 64;
+
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| input >> bit & 1 == 1)
+            .fold(self.constant, |acc, (_, column)| acc ^ column)
+    }
+}
+
+/// gaussian elimination over GF(2), returning the rank of a set of columns and a basis of the
+/// combinations of them that sum to zero
+pub fn eliminate(columns: &[u64]) -> (usize, Vec<u128>) {
+    // reduced columns, each with the combination of original columns it is the sum of, indexed
+    // by their lowest set bit
+    let mut pivots: [Option<(u64, u128)>; 64] = [None; 64];
+    let mut kernel = Vec::new();
+
+    for (idx, column) in columns.iter().enumerate() {
+        let (mut column, mut combination) = (*column, 1u128 << idx);
+
+        while column != 0 {
+            let pivot = column.trailing_zeros() as usize;
+            match pivots[pivot] {
+                Some((other, other_combination)) => {
+                    column ^= other;
+                    combination ^= other_combination;
+                }
+                None => {
+                    pivots[pivot] = Some((column, combination));
+                    break;
+                }
+            }
+        }
+
+        if column == 0 {
+            kernel.push(combination);
+        }
+    }
+
+    (columns.len() - kernel.len(), kernel)
+}
+
+impl fmt::Display for Linearity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "affine over GF(2), rank {} of {} (state rank {} of 64), constant {:#x}",
+            self.rank, INPUTS, self.state_rank, self.constant
+        )?;
+        write!(f, "the byte bits flip the output bits")?;
+        for (bit, column) in self.columns[64..].iter().enumerate() {
+            write!(f, "\n\tbyte bit {}: {:#018x}", bit, column)?;
+        }
+        writeln!(f)?;
+        write!(f, "kernel of dimension {}", self.kernel.len())?;
+        for diff in &self.kernel {
+            write!(
+                f,
+                "\n\tstate ^ {:#018x}, byte ^ {:#04x}",
+                *diff as u64,
+                (diff >> 64) as u8
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::parse;
+    use rand::prelude::*;
+
+    fn rand_affine<R: Rng>(rng: &mut R, depth: usize) -> Expr<Tag> {
+        match rng.gen_range(0..if depth == 0 { 3 } else { 6 }) {
+            0 => Expr::Tag(Tag::HashState),
+            1 => Expr::Tag(Tag::Byte),
+            2 => Expr::Tag(Tag::Const(rng.gen())),
+            3 => Expr::Xor(
+                Box::new(rand_affine(rng, depth - 1)),
+                Box::new(rand_affine(rng, depth - 1)),
+            ),
+            4 => Expr::RotLeft(
+                Box::new(rand_affine(rng, depth - 1)),
+                Box::new(Expr::Tag(Tag::Const(rng.gen()))),
+            ),
+            _ => Expr::RotRight(
+                Box::new(rand_affine(rng, depth - 1)),
+                Box::new(Expr::Tag(Tag::Const(rng.gen()))),
+            ),
+        }
+    }
+
+    #[test]
+    fn matrix_matches_eval() {
+        let mut rng = thread_rng();
+
+        for _ in 0..200 {
+            let expr = rand_affine(&mut rng, 6);
+            let linearity = Linearity::of(&expr).unwrap();
+
+            for _ in 0..100 {
+                let (state, byte) = (rng.gen(), rng.gen());
+                assert_eq!(
+                    linearity.apply(state, byte),
+                    expr.hash_bytes(state, &[byte]),
+                    "{}",
+                    expr
+                );
+            }
+            for diff in &linearity.kernel {
+                let (state, byte) = (rng.gen::<u64>(), rng.gen::<u8>());
+                assert_eq!(
+                    linearity.apply(state, byte),
+                    linearity.apply(state ^ *diff as u64, byte ^ (diff >> 64) as u8)
+                );
+            }
+            assert_eq!(linearity.rank + linearity.kernel.len(), INPUTS);
+        }
+    }
+
+    #[test]
+    fn ranks_and_parts() {
+        let expr = parse("((state << 5) xor byte)").unwrap();
+        let linearity = Linearity::of(&expr).unwrap();
+        assert_eq!((linearity.rank, linearity.state_rank), (64, 64));
+        assert_eq!(linearity.kernel.len(), 8);
+
+        // states of all zeros and all ones collide
+        let expr = parse("((state xor byte) xor (state >> 1))").unwrap();
+        assert_eq!(Linearity::of(&expr).unwrap().state_rank, 63);
+
+        // states with equal halves collide
+        let expr = parse("(state xor (state << 32))").unwrap();
+        assert_eq!(Linearity::of(&expr).unwrap().state_rank, 32);
+
+        let expr = parse("(((state xor byte) + 5) xor (state << (3 + 4)))").unwrap();
+        assert!(Linearity::of(&expr).is_none());
+        let parts: Vec<_> = affine_parts(&expr).iter().map(|e| e.to_string()).collect();
+        assert_eq!(parts, ["(state xor byte)", "(state << (3 + 4))"]);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod analysis;
+pub mod bijective;
 pub mod closure;
 pub mod expr;
+pub mod inverse;
+pub mod linear;
 pub mod parse;
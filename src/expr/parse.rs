@@ -30,6 +30,11 @@ pub fn parse(text: &str) -> Result<Expr<Tag>> {
     }
 }
 
+/// parse a shape like `((_ + _) << _)`, any tags are dropped
+pub fn parse_shape(text: &str) -> Result<Expr<()>> {
+    parse(&text.replace('_', "0")).map(|expr| expr.shape())
+}
+
 fn parse_expr(text: &[u8]) -> Result<(&[u8], Expr<Tag>)> {
     parse_binary_operator(text)
         .or_else(|_| parse_const(text))
@@ -0,0 +1,133 @@
+use super::Hash;
+use rand::prelude::*;
+use std::fmt;
+
+/// the largest bias that passes the strict avalanche criterion in smhasher
+pub const SMHASHER_MAX_BIAS: f64 = 0.01;
+
+/// the probability that flipping each input bit flips each output bit, for keys of a fixed length
+pub struct Avalanche {
+    pub key_len: usize,
+    pub samples: usize,
+    /// indexed by input bit, then output bit
+    pub matrix: Vec<[f64; 64]>,
+}
+
+impl Avalanche {
+    /// hash `samples` random keys of `key_len` bytes, and every key with each of its bits flipped
+    pub fn measure<H: Hash, R: Rng>(
+        hasher: &H,
+        init: u64,
+        key_len: usize,
+        samples: usize,
+        rng: &mut R,
+    ) -> Avalanche {
+        let mut flips = vec![[0usize; 64]; key_len * 8];
+        let mut key = vec![0u8; key_len];
+
+        for _ in 0..samples {
+            rng.fill_bytes(&mut key);
+            let hash = hasher.hash_bytes(init, &key);
+
+            for (bit, counts) in flips.iter_mut().enumerate() {
+                key[bit / 8] ^= 1 << (bit % 8);
+                let diff = hash ^ hasher.hash_bytes(init, &key);
+                key[bit / 8] ^= 1 << (bit % 8);
+
+                for (out, count) in counts.iter_mut().enumerate() {
+                    *count += (diff >> out) as usize & 1;
+                }
+            }
+        }
+
+        Avalanche {
+            key_len,
+            samples,
+            matrix: flips
+                .iter()
+                .map(|counts| counts.map(|count| count as f64 / samples as f64))
+                .collect(),
+        }
+    }
+
+    /// how far the flip probability of an input and output bit is from one half, scaled so that
+    /// 0 is unbiased and 1 means the output bit always or never flips
+    pub fn bias(&self, input: usize, output: usize) -> f64 {
+        (self.matrix[input][output] * 2. - 1.).abs()
+    }
+
+    fn biases(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        (0..self.matrix.len())
+            .flat_map(|input| (0..64).map(move |output| (input, output)))
+            .map(|(input, output)| (input, output, self.bias(input, output)))
+    }
+
+    /// the most biased pair of input and output bits, and its bias
+    pub fn worst(&self) -> (usize, usize, f64) {
+        self.biases().fold(
+            (0, 0, 0.),
+            |worst, bias| if bias.2 > worst.2 { bias } else { worst },
+        )
+    }
+
+    pub fn worst_bias(&self) -> f64 {
+        self.worst().2
+    }
+
+    pub fn mean_bias(&self) -> f64 {
+        self.biases().map(|(_, _, bias)| bias).sum::<f64>() / (self.matrix.len() * 64) as f64
+    }
+
+    /// whether the worst bias is within smhasher's threshold, which needs on the order of a
+    /// hundred thousand samples to not fail from noise alone
+    pub fn passes(&self) -> bool {
+        self.worst_bias() <= SMHASHER_MAX_BIAS
+    }
+}
+
+impl fmt::Display for Avalanche {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (input, output, worst) = self.worst();
+
+        writeln!(
+            f,
+            "avalanche of {} byte keys over {} samples",
+            self.key_len, self.samples
+        )?;
+        writeln!(
+            f,
+            "\tworst bias {:.2}% (input bit {} -> output bit {})",
+            worst * 100.,
+            input,
+            output
+        )?;
+        writeln!(f, "\tmean bias {:.2}%", self.mean_bias() * 100.)?;
+        write!(f, "\t{}", if self.passes() { "pass" } else { "FAIL" })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hash::test::splitmix;
+    use crate::seed;
+
+    #[test]
+    fn xor_fails() {
+        let hasher: fn(u64, u64) -> u64 = |state, byte| state ^ byte;
+        let avalanche = Avalanche::measure(&hasher, 0, 2, 1000, &mut seed::rng(0));
+
+        assert!(!avalanche.passes());
+        assert_eq!(avalanche.worst_bias(), 1.);
+        assert!(avalanche.mean_bias() > 0.99);
+    }
+
+    #[test]
+    fn splitmix_passes() {
+        let hasher = splitmix as fn(u64, u64) -> u64;
+        let avalanche = Avalanche::measure(&hasher, 0, 4, 200_000, &mut seed::rng(0));
+
+        assert!(avalanche.passes(), "{}", avalanche);
+        assert!(avalanche.mean_bias() < 0.005, "{}", avalanche);
+    }
+}
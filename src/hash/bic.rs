@@ -0,0 +1,143 @@
+use super::Hash;
+use rand::prelude::*;
+use std::fmt;
+
+/// the bit independence criterion: how correlated the flips of each pair of output bits are when
+/// a single input bit is flipped
+pub struct Bic {
+    pub key_len: usize,
+    pub samples: usize,
+    /// the input bit and pair of output bits with the strongest correlation
+    pub worst: (usize, usize, usize),
+    /// the absolute correlation of the worst pair, between 0 and 1
+    pub worst_correlation: f64,
+    pub mean_correlation: f64,
+}
+
+impl Bic {
+    /// hash `samples` random keys of `key_len` bytes, and every key with each of its bits flipped
+    pub fn measure<H: Hash, R: Rng>(
+        hasher: &H,
+        init: u64,
+        key_len: usize,
+        samples: usize,
+        rng: &mut R,
+    ) -> Bic {
+        let mut key = vec![0u8; key_len];
+        let mut bic = Bic {
+            key_len,
+            samples,
+            worst: (0, 0, 0),
+            worst_correlation: 0.,
+            mean_correlation: 0.,
+        };
+
+        for input in 0..key_len * 8 {
+            // the number of times each output bit flipped, and each pair of output bits flipped
+            // together
+            let mut single = [0usize; 64];
+            let mut both = vec![[0usize; 64]; 64];
+
+            for _ in 0..samples {
+                rng.fill_bytes(&mut key);
+                let hash = hasher.hash_bytes(init, &key);
+                key[input / 8] ^= 1 << (input % 8);
+                let diff = hash ^ hasher.hash_bytes(init, &key);
+
+                for a in set_bits(diff) {
+                    single[a] += 1;
+                    for b in set_bits(diff >> a >> 1) {
+                        both[a][a + b + 1] += 1;
+                    }
+                }
+            }
+
+            for a in 0..64 {
+                for b in a + 1..64 {
+                    let corr = correlation(samples, single[a], single[b], both[a][b]).abs();
+                    bic.mean_correlation += corr;
+
+                    if corr > bic.worst_correlation {
+                        bic.worst_correlation = corr;
+                        bic.worst = (input, a, b);
+                    }
+                }
+            }
+        }
+
+        bic.mean_correlation /= (key_len * 8 * 64 * 63 / 2).max(1) as f64;
+        bic
+    }
+}
+
+fn set_bits(mut bits: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bits == 0 {
+            None
+        } else {
+            let bit = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(bit)
+        }
+    })
+}
+
+/// the phi coefficient of two bits that were set `a` and `b` times, and `both` times together
+fn correlation(samples: usize, a: usize, b: usize, both: usize) -> f64 {
+    let (n, a, b, both) = (samples as f64, a as f64, b as f64, both as f64);
+    let spread = (a * (n - a) * b * (n - b)).sqrt();
+
+    if spread == 0. {
+        // a bit that always or never flips is as dependent as it gets
+        1.
+    } else {
+        (n * both - a * b) / spread
+    }
+}
+
+impl fmt::Display for Bic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (input, a, b) = self.worst;
+
+        writeln!(
+            f,
+            "bit independence of {} byte keys over {} samples",
+            self.key_len, self.samples
+        )?;
+        writeln!(
+            f,
+            "\tworst correlation {:.3} (input bit {} -> output bits {} and {})",
+            self.worst_correlation, input, a, b
+        )?;
+        write!(f, "\tmean correlation {:.3}", self.mean_correlation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hash::test::splitmix;
+    use crate::seed;
+
+    #[test]
+    fn copied_bits_correlate() {
+        let hasher: fn(u64, u64) -> u64 = |state, byte| {
+            let mixed = splitmix(state, byte);
+            mixed << 32 | mixed & 0xffff_ffff
+        };
+        let bic = Bic::measure(&hasher, 0, 2, 1000, &mut seed::rng(0));
+
+        assert_eq!(bic.worst_correlation, 1., "{}", bic);
+        assert_eq!(bic.worst.2, bic.worst.1 + 32, "{}", bic);
+    }
+
+    #[test]
+    fn splitmix_is_independent() {
+        // a single round leaves some output pairs correlated when the last byte flips
+        let hasher: fn(u64, u64) -> u64 = |state, byte| splitmix(splitmix(state, byte), 0);
+        let bic = Bic::measure(&hasher, 0, 2, 10_000, &mut seed::rng(0));
+
+        assert!(bic.worst_correlation < 0.1, "{}", bic);
+        assert!(bic.mean_correlation < 0.02, "{}", bic);
+    }
+}
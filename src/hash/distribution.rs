@@ -0,0 +1,193 @@
+use super::Hash;
+use std::fmt;
+
+/// the numbers of bucket bits that are tested by default
+pub const BUCKET_BITS: [u32; 4] = [8, 10, 12, 16];
+
+/// which bits of a hash pick the bucket
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Select {
+    /// `hash & (2^k - 1)`, the usual way to index a power of two table
+    Low,
+    /// `hash >> (64 - k)`
+    High,
+}
+
+/// how evenly a set of keys spreads over `2^bits` buckets
+pub struct Buckets {
+    pub bits: u32,
+    pub select: Select,
+    pub keys: usize,
+    pub chi_squared: f64,
+    pub expected_collisions: f64,
+    pub collisions: usize,
+    pub max_load: usize,
+}
+
+/// bucket reports for several table sizes, from both ends of the hash
+pub struct Distribution {
+    pub buckets: Vec<Buckets>,
+}
+
+impl Select {
+    fn bucket(self, hash: u64, bits: u32) -> usize {
+        match self {
+            Select::Low => (hash & ((1 << bits) - 1)) as usize,
+            Select::High => hash.checked_shr(64 - bits).unwrap_or(0) as usize,
+        }
+    }
+}
+
+impl Buckets {
+    pub fn measure(hashes: &[u64], bits: u32, select: Select) -> Buckets {
+        let mut loads = vec![0usize; 1 << bits];
+        for hash in hashes {
+            loads[select.bucket(*hash, bits)] += 1;
+        }
+
+        let keys = hashes.len() as f64;
+        let buckets = loads.len() as f64;
+        let expected = keys / buckets;
+
+        Buckets {
+            bits,
+            select,
+            keys: hashes.len(),
+            chi_squared: loads
+                .iter()
+                .map(|load| (*load as f64 - expected).powi(2) / expected)
+                .sum(),
+            expected_collisions: keys - buckets * (1. - (1. - 1. / buckets).powf(keys)),
+            collisions: hashes.len() - loads.iter().filter(|load| **load > 0).count(),
+            max_load: loads.iter().copied().max().unwrap_or(0),
+        }
+    }
+
+    /// chi squared divided by the degrees of freedom, which is close to 1 for uniform hashes and
+    /// grows as the buckets get more uneven
+    pub fn normalized_chi_squared(&self) -> f64 {
+        self.chi_squared / ((1usize << self.bits) - 1) as f64
+    }
+}
+
+impl Distribution {
+    /// hash every key and measure the buckets for every table size that the keys can fill
+    pub fn measure<'a, H: Hash>(
+        hasher: &H,
+        init: u64,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Distribution {
+        let hashes: Vec<_> = keys
+            .into_iter()
+            .map(|key| hasher.hash_bytes(init, key))
+            .collect();
+
+        Distribution::from_hashes(&hashes)
+    }
+
+    /// measure the buckets of hashes that have already been computed
+    pub fn from_hashes(hashes: &[u64]) -> Distribution {
+        let buckets = BUCKET_BITS
+            .iter()
+            .filter(|bits| 1 << **bits <= hashes.len())
+            .flat_map(|bits| {
+                [Select::Low, Select::High].map(|select| Buckets::measure(hashes, *bits, select))
+            })
+            .collect();
+
+        Distribution { buckets }
+    }
+
+    /// a score between 0 and 1, where 1 means every table size looks uniform
+    pub fn score(&self) -> f64 {
+        let total: f64 = self
+            .buckets
+            .iter()
+            .map(|buckets| 1. / buckets.normalized_chi_squared().max(1.))
+            .sum();
+
+        total / self.buckets.len().max(1) as f64
+    }
+}
+
+impl fmt::Display for Select {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Select::Low => write!(f, "low"),
+            Select::High => write!(f, "high"),
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "bits\tselect\tkeys\tchi^2/df\tcollisions (expected)\tmax load"
+        )?;
+        for buckets in &self.buckets {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{:.3}\t\t{} ({:.0})\t\t{}",
+                buckets.bits,
+                buckets.select,
+                buckets.keys,
+                buckets.normalized_chi_squared(),
+                buckets.collisions,
+                buckets.expected_collisions,
+                buckets.max_load
+            )?;
+        }
+        write!(f, "score {:.3}", self.score())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn even_and_degenerate_buckets() {
+        let even: Vec<u64> = (0..1024).collect();
+        let low = Buckets::measure(&even, 8, Select::Low);
+        assert_eq!(low.chi_squared, 0.);
+        assert_eq!((low.collisions, low.max_load), (1024 - 256, 4));
+
+        let high = Buckets::measure(&even, 8, Select::High);
+        assert_eq!((high.collisions, high.max_load), (1023, 1024));
+
+        let spread: Vec<u64> = even.iter().map(|hash| hash << 54).collect();
+        assert_eq!(Buckets::measure(&spread, 10, Select::High).max_load, 1);
+    }
+
+    #[test]
+    fn table_sizes_and_expected_collisions() {
+        let even: Vec<u64> = (0..1024).collect();
+        let distribution = Distribution::from_hashes(&even);
+
+        // only the tables the keys can fill are measured
+        let sizes: Vec<_> = distribution
+            .buckets
+            .iter()
+            .map(|buckets| (buckets.bits, buckets.select))
+            .collect();
+        assert_eq!(
+            sizes,
+            [
+                (8, Select::Low),
+                (8, Select::High),
+                (10, Select::Low),
+                (10, Select::High)
+            ]
+        );
+
+        // n random keys in n buckets leave about n / e of them colliding
+        let full = &distribution.buckets[2];
+        assert!((full.expected_collisions - 1024. / std::f64::consts::E).abs() < 1.);
+        assert_eq!(full.collisions, 0);
+
+        // the low bits are uniform, the high bits put every key in the first bucket
+        assert_eq!(distribution.buckets[3].normalized_chi_squared(), 1024.);
+        assert_eq!(distribution.score(), (2. + 2. / 1024.) / 4.);
+    }
+}
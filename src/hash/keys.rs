@@ -0,0 +1,290 @@
+use rand::prelude::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// a family of keys to hash, modelled on the kinds of keys that end up in real hash tables
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeySet {
+    /// uniformly random bytes
+    Random { len: usize },
+    /// consecutive integers written in decimal, like `"1041"`
+    Decimal,
+    /// consecutive integers written in lowercase hex, like `"411"`
+    Hex,
+    /// consecutive little endian integers, truncated to `len` bytes
+    Sequential { len: usize },
+    /// english words, with suffixes and compounds once the word list runs out
+    Words,
+    /// random version 4 uuids in their usual text form
+    Uuid,
+    /// unix style file paths
+    Paths,
+    /// dotted quad ipv4 addresses
+    Ipv4,
+    /// `len` zero bytes with `bits` random bits set
+    Sparse { len: usize, bits: usize },
+}
+
+/// how the keys in a corpus file are separated
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// one key per line
+    Lines,
+    /// every key is preceded by its length as a little endian u32
+    LengthPrefixed,
+}
+
+#[rustfmt::skip]
+const WORDS: &[&str] = &[
+    "the", "of", "and", "to", "in", "is", "you", "that", "it", "he", "was", "for", "on", "are",
+    "as", "with", "his", "they", "at", "be", "this", "have", "from", "or", "one", "had", "by",
+    "word", "but", "not", "what", "all", "were", "we", "when", "your", "can", "said", "there",
+    "use", "an", "each", "which", "she", "do", "how", "their", "if", "will", "up", "other", "about",
+    "out", "many", "then", "them", "these", "so", "some", "her", "would", "make", "like", "him",
+    "into", "time", "has", "look", "two", "more", "write", "go", "see", "number", "no", "way",
+    "could", "people", "my", "than", "first", "water", "been", "call", "who", "oil", "its", "now",
+    "find", "long", "down", "day", "did", "get", "come", "made", "may", "part", "over", "new",
+    "sound", "take", "only", "little", "work", "know", "place", "year", "live", "me", "back",
+    "give", "most", "very", "after", "thing", "our", "just", "name", "good", "sentence", "man",
+    "think", "say", "great", "where", "help", "through", "much", "before", "line", "right", "too",
+    "mean", "old", "any", "same", "tell", "boy", "follow", "came", "want", "show", "also", "around",
+    "form", "three", "small", "set", "put", "end", "does", "another", "well", "large", "must",
+    "big", "even", "such", "because", "turn", "here", "why", "ask", "went", "men", "read", "need",
+    "land", "different", "home", "us", "move", "try", "kind", "hand", "picture", "again", "change",
+    "off", "play", "spell", "air", "away", "animal", "house", "point", "page", "letter", "mother",
+    "answer", "found", "study", "still", "learn", "should", "america", "world", "hash", "table",
+    "bucket", "key", "value", "seed",
+];
+
+const SUFFIXES: &[&str] = &["", "s", "ed", "ing", "er", "ly", "ness", "able"];
+
+#[rustfmt::skip]
+const DIRECTORIES: &[&str] = &[
+    "usr", "lib", "bin", "src", "home", "etc", "var", "log", "share", "include", "target", "debug",
+    "release", "tmp", "cache", "config", "local", "opt", "docs", "tests",
+];
+
+#[rustfmt::skip]
+const EXTENSIONS: &[&str] = &[
+    "rs", "c", "h", "txt", "md", "toml", "json", "so", "o", "log",
+];
+
+impl KeySet {
+    /// generate `count` keys, consecutive key sets start from a random offset
+    pub fn generate<R: Rng>(&self, count: usize, rng: &mut R) -> Vec<Vec<u8>> {
+        let start: u64 = rng.gen::<u32>().into();
+
+        (0..count as u64)
+            .map(|idx| match *self {
+                KeySet::Random { len } => (0..len).map(|_| rng.gen()).collect(),
+                KeySet::Decimal => (start + idx).to_string().into_bytes(),
+                KeySet::Hex => format!("{:x}", start + idx).into_bytes(),
+                KeySet::Sequential { len } => {
+                    let bytes = (start + idx).to_le_bytes();
+                    bytes[..len.min(8)].to_vec()
+                }
+                KeySet::Words => word(idx as usize, rng).into_bytes(),
+                KeySet::Uuid => uuid(rng).into_bytes(),
+                KeySet::Paths => path(rng).into_bytes(),
+                KeySet::Ipv4 => {
+                    let [a, b, c, d] = rng.gen::<[u8; 4]>();
+                    format!("{}.{}.{}.{}", a, b, c, d).into_bytes()
+                }
+                KeySet::Sparse { len, bits } => {
+                    let mut key = vec![0u8; len];
+                    for _ in 0..bits {
+                        let bit = rng.gen_range(0..len * 8);
+                        key[bit / 8] |= 1 << (bit % 8);
+                    }
+                    key
+                }
+            })
+            .collect()
+    }
+}
+
+fn word<R: Rng>(idx: usize, rng: &mut R) -> String {
+    let base = WORDS[idx % WORDS.len()];
+    let suffix = SUFFIXES[idx / WORDS.len() % SUFFIXES.len()];
+
+    if idx < WORDS.len() * SUFFIXES.len() {
+        format!("{}{}", base, suffix)
+    } else {
+        format!("{}{}{}", base, WORDS.choose(rng).unwrap(), suffix)
+    }
+}
+
+fn uuid<R: Rng>(rng: &mut R) -> String {
+    let mut bytes: [u8; 16] = rng.gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn path<R: Rng>(rng: &mut R) -> String {
+    let mut path = String::new();
+
+    for _ in 0..rng.gen_range(1..6) {
+        path.push('/');
+        path.push_str(DIRECTORIES.choose(rng).unwrap());
+    }
+    path.push('/');
+    path.push_str(WORDS.choose(rng).unwrap());
+    path.push('.');
+    path.push_str(EXTENSIONS.choose(rng).unwrap());
+
+    path
+}
+
+/// read a corpus of keys from a file
+pub fn load(path: impl AsRef<Path>, format: Format) -> io::Result<Vec<Vec<u8>>> {
+    let bytes = fs::read(path)?;
+
+    match format {
+        Format::Lines => Ok(bytes
+            .split(|byte| *byte == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .filter(|line| !line.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect()),
+        Format::LengthPrefixed => {
+            let mut keys = Vec::new();
+            let mut rest = bytes.as_slice();
+
+            while !rest.is_empty() {
+                let key = rest
+                    .get(..4)
+                    .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+                    .and_then(|len| rest.get(4..4 + len))
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "truncated length prefixed key")
+                    })?;
+
+                keys.push(key.to_vec());
+                rest = &rest[4 + key.len()..];
+            }
+
+            Ok(keys)
+        }
+    }
+}
+
+/// get keys from a key set name like `words` or `random:16`, or from a corpus file given as
+/// `lines:<path>` or `prefixed:<path>`
+pub fn from_spec<R: Rng>(spec: &str, count: usize, rng: &mut R) -> Result<Vec<Vec<u8>>, String> {
+    let file = |path, format| load(path, format).map_err(|err| format!("{}: {}", path, err));
+
+    match spec.split_once(':') {
+        Some(("lines", path)) => file(path, Format::Lines),
+        Some(("prefixed", path)) => file(path, Format::LengthPrefixed),
+        _ => Ok(spec.parse::<KeySet>()?.generate(count, rng)),
+    }
+}
+
+impl FromStr for KeySet {
+    type Err = String;
+
+    /// parse a key set from its name, with lengths given after colons, like `random:16` or
+    /// `sparse:32:3`
+    fn from_str(text: &str) -> Result<KeySet, String> {
+        let mut parts = text.split(':');
+        let name = parts.next().unwrap_or("");
+        let mut num = |default: usize| -> Result<usize, String> {
+            parts.next().map_or(Ok(default), |num| {
+                num.parse()
+                    .map_err(|_| format!("`{}` is not a number in `{}`", num, text))
+            })
+        };
+
+        match name {
+            "random" => Ok(KeySet::Random { len: num(16)? }),
+            "decimal" => Ok(KeySet::Decimal),
+            "hex" => Ok(KeySet::Hex),
+            "sequential" => Ok(KeySet::Sequential { len: num(8)? }),
+            "words" => Ok(KeySet::Words),
+            "uuid" => Ok(KeySet::Uuid),
+            "paths" => Ok(KeySet::Paths),
+            "ipv4" => Ok(KeySet::Ipv4),
+            "sparse" => {
+                let (len, bits) = (num(32)?, num(3)?);
+                if len == 0 || bits > len * 8 {
+                    return Err(format!(
+                        "`{}` needs a key of at least one byte and at most 8 bits set per byte",
+                        text
+                    ));
+                }
+                Ok(KeySet::Sparse { len, bits })
+            }
+            _ => Err(format!("unknown key set `{}`", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_sets() {
+        let mut rng = thread_rng();
+
+        for key in KeySet::Uuid.generate(100, &mut rng) {
+            assert_eq!(key.len(), 36);
+        }
+        for key in KeySet::Ipv4.generate(100, &mut rng) {
+            let text = String::from_utf8(key).unwrap();
+            assert!(text.parse::<std::net::Ipv4Addr>().is_ok(), "{}", text);
+        }
+
+        let words = KeySet::Words.generate(10_000, &mut rng);
+        let unique: std::collections::HashSet<_> = words.iter().collect();
+        assert!(unique.len() > 9_000);
+
+        let decimal = KeySet::Decimal.generate(2, &mut rng);
+        let [a, b]: [u64; 2] = [0, 1].map(|idx| {
+            String::from_utf8(decimal[idx].clone())
+                .unwrap()
+                .parse()
+                .unwrap()
+        });
+        assert_eq!(a + 1, b);
+
+        assert!(KeySet::Sparse { len: 32, bits: 3 }
+            .generate(100, &mut rng)
+            .iter()
+            .all(|key| key.iter().map(|byte| byte.count_ones()).sum::<u32>() <= 3));
+        assert!("sparse:0:3".parse::<KeySet>().is_err());
+        assert!("sparse:2:17".parse::<KeySet>().is_err());
+        assert!("sparse:2:16".parse::<KeySet>().is_ok());
+    }
+
+    #[test]
+    fn length_prefixed_corpus() {
+        let path = std::env::temp_dir().join(format!("best-hash-corpus-{}", std::process::id()));
+        let mut bytes = Vec::new();
+        for key in ["a", "", "hello"] {
+            bytes.extend((key.len() as u32).to_le_bytes());
+            bytes.extend(key.as_bytes());
+        }
+        fs::write(&path, &bytes).unwrap();
+
+        let keys = load(&path, Format::LengthPrefixed).unwrap();
+        assert_eq!(keys, [&b"a"[..], b"", b"hello"]);
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(load(&path, Format::LengthPrefixed).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+macro_rules! count_ones {
+    ($bytes:expr) => {{
+        let mut bytes = $bytes;
+        let mut count = 0;
+
+        while bytes > 0 {
+            count += 1;
+            bytes &= bytes - 1;
+        }
+
+        count
+    }};
+}
+
+pub mod avalanche;
+pub mod bic;
+pub mod distribution;
+pub mod keys;
+pub mod smhasher;
+pub mod throughput;
+
+use bic::Bic;
+use distribution::Distribution;
+use rand::prelude::*;
+use std::rc::Rc;
+
+pub trait Hash {
+    fn hash_bytes(&self, init: u64, bytes: &[u8]) -> u64;
+}
+
+/// hash `N` equal length buffers in lockstep, one per lane
+pub trait BatchHash<const N: usize> {
+    fn hash_batch(&self, init: u64, inputs: &[&[u8]; N]) -> [u64; N];
+}
+
+/// an optional term added to the score from `score_hasher`, higher scores are better
+#[derive(Clone, Debug)]
+pub enum Metric {
+    /// reward output bits that flip independently of each other, see `bic::Bic`, scored on the
+    /// mean correlation since the worst pair is close to 1 for all but the best hashes
+    Bic {
+        key_len: usize,
+        samples: usize,
+        weight: f64,
+    },
+    /// reward hashes that spread a set of keys evenly over power of two tables, see
+    /// `distribution::Distribution`
+    Distribution { keys: Rc<[Vec<u8>]>, weight: f64 },
+    /// reward hashes where flipping random bits of a set of keys flips half the output bits
+    KeyAvalanche {
+        keys: Rc<[Vec<u8>]>,
+        mutations: usize,
+        weight: f64,
+    },
+}
+
+impl Metric {
+    pub fn score<H: Hash, R: Rng>(&self, hasher: &H, init: u64, rng: &mut R) -> f64 {
+        match self {
+            Metric::Bic {
+                key_len,
+                samples,
+                weight,
+            } => {
+                let bic = Bic::measure(hasher, init, *key_len, *samples, rng);
+                weight * (1. - bic.mean_correlation)
+            }
+            Metric::Distribution { keys, weight } => {
+                weight * Distribution::measure(hasher, init, keys.iter().map(Vec::as_slice)).score()
+            }
+            Metric::KeyAvalanche {
+                keys,
+                mutations,
+                weight,
+            } => {
+                let mut flipped = 0f64;
+                let mut tested = 0;
+
+                for key in keys.iter().filter(|key| !key.is_empty()) {
+                    let hash = hasher.hash_bytes(init, key);
+                    let new_hash = hasher.hash_bytes(init, &mutate(key, *mutations, rng));
+                    flipped += f64::from(count_ones!(hash ^ new_hash));
+                    tested += 1;
+                }
+
+                // the ideal is flipping 32 of the 64 output bits on average
+                let average = flipped / f64::from(tested.max(1));
+                weight * (1. - (average - 32.).abs() / 32.)
+            }
+        }
+    }
+}
+
+/// the number of lanes `score_hasher` hashes at once
+pub const LANES: usize = 8;
+
+impl Hash for fn(u64, u64) -> u64 {
+    fn hash_bytes(&self, init: u64, bytes: &[u8]) -> u64 {
+        let mut hash = init;
+
+        for byte in bytes {
+            hash = (self)(hash, *byte as u64);
+        }
+
+        hash
+    }
+}
+
+impl<const N: usize> BatchHash<N> for fn(u64, u64) -> u64 {
+    /// call the function on every lane in turn, so the lanes' chains of calls overlap
+    fn hash_batch(&self, init: u64, inputs: &[&[u8]; N]) -> [u64; N] {
+        let len = inputs.first().map_or(0, |bytes| bytes.len());
+        assert!(
+            inputs.iter().all(|bytes| bytes.len() == len),
+            "batched inputs have to be the same length"
+        );
+
+        let mut hash = [init; N];
+
+        for idx in 0..len {
+            for (hash, bytes) in hash.iter_mut().zip(inputs) {
+                *hash = (self)(*hash, bytes[idx] as u64);
+            }
+        }
+
+        hash
+    }
+}
+
+/// score how many output bits flip when a few bits of random buffers are flipped, hashing each
+/// cluster's mutated buffers `LANES - 1` at a time next to the buffer they were mutated from
+#[allow(clippy::too_many_arguments)]
+pub fn score_hasher<H: BatchHash<LANES>, R: Rng>(
+    hasher: H,
+    len: usize,
+    init: u64,
+    clusters: usize,
+    cluster_size: usize,
+    bytes_len: usize,
+    mutations: usize,
+    rng: &mut R,
+) -> f64 {
+    let mut score = 0f64;
+
+    for _ in 0..clusters {
+        let bytes: Vec<u8> = (0..bytes_len).map(|_| rng.gen()).collect();
+        let mutated: Vec<_> = (0..cluster_size)
+            .map(|_| mutate(&bytes, mutations, rng))
+            .collect();
+
+        // the first lane of every batch hashes the original bytes, to compare the others against
+        for chunk in mutated.chunks(LANES - 1) {
+            let mut inputs = [bytes.as_slice(); LANES];
+            for (lane, new_bytes) in inputs[1..].iter_mut().zip(chunk) {
+                *lane = new_bytes;
+            }
+
+            let hashes = hasher.hash_batch(init, &inputs);
+            for new_bytes_hash in &hashes[1..=chunk.len()] {
+                let hash_diff = count_ones!(hashes[0] ^ new_bytes_hash); // the number of bits that are different in the hash
+                score += hash_diff as f64 / mutations as f64;
+            }
+        }
+    }
+
+    score / clusters as f64 / cluster_size as f64 / (len as f64).sqrt()
+}
+
+/// flip `mutations` random bits in random bytes of a copy of `bytes`
+fn mutate<R: Rng>(bytes: &[u8], mutations: usize, rng: &mut R) -> Vec<u8> {
+    let mut new_bytes = bytes.to_vec();
+
+    for _ in 0..mutations {
+        let byte = rng.gen::<usize>() % bytes.len();
+        let bit = rng.gen::<u8>() % 8;
+        new_bytes[byte] ^= 1 << bit;
+    }
+
+    new_bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::closure::Hasher;
+    use crate::expr::expr::Expr;
+    use crate::seed;
+    use crate::ssa::code::Function;
+    use crate::ssa::gen::emit;
+
+    /// a strong mixing function that isn't limited to adds, rotations and xors
+    pub fn splitmix(state: u64, byte: u64) -> u64 {
+        let mut z = (state ^ byte).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// `score_hasher` hashing one buffer at a time
+    fn score_one_by_one<H: Hash, R: Rng>(hasher: &H, len: usize, rng: &mut R) -> f64 {
+        let mut score = 0f64;
+
+        for _ in 0..10 {
+            let bytes: Vec<u8> = (0..50).map(|_| rng.gen()).collect();
+            let bytes_hash = hasher.hash_bytes(0, &bytes);
+
+            for _ in 0..20 {
+                let new_bytes = mutate(&bytes, 3, rng);
+                score += f64::from(count_ones!(bytes_hash ^ hasher.hash_bytes(0, &new_bytes))) / 3.;
+            }
+        }
+
+        score / 10. / 20. / (len as f64).sqrt()
+    }
+
+    #[test]
+    fn batches_score_the_same() {
+        let mut rng = thread_rng();
+
+        for _ in 0..200 {
+            let expr = Expr::rand(&mut rng);
+            let prog = emit(&Function::from(&expr).optimize());
+            let seed = rng.gen();
+
+            let expected = score_one_by_one(&expr, expr.len(), &mut seed::rng(seed));
+            let closure = score_hasher(
+                Hasher::from(&expr),
+                expr.len(),
+                0,
+                10,
+                20,
+                50,
+                3,
+                &mut seed::rng(seed),
+            );
+            let vm = score_hasher(prog, expr.len(), 0, 10, 20, 50, 3, &mut seed::rng(seed));
+            assert_eq!(closure, expected, "{}", expr);
+            assert_eq!(vm, expected, "{}", expr);
+        }
+    }
+}
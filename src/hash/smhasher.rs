@@ -0,0 +1,327 @@
+use super::distribution::Distribution;
+use super::Hash;
+use rand::prelude::*;
+use std::collections::HashSet;
+use std::fmt;
+
+/// the result of hashing one key set
+pub struct TestResult {
+    pub name: String,
+    pub keys: usize,
+    /// collisions of the full 64 bit hashes
+    pub collisions: usize,
+    /// collisions of the low 32 bits, and how many a random function would have
+    pub collisions_32: usize,
+    pub expected_32: f64,
+    /// the worst chi squared per degree of freedom over the table sizes the keys can fill, and
+    /// the largest value that a random function would plausibly reach
+    pub worst_chi_squared: Option<(f64, f64)>,
+}
+
+/// the results of every test in the battery
+pub struct Report {
+    pub results: Vec<TestResult>,
+}
+
+impl TestResult {
+    fn measure(name: String, hashes: &[u64]) -> TestResult {
+        let buckets = Distribution::from_hashes(hashes).buckets;
+        let worst = buckets
+            .iter()
+            .map(|buckets| {
+                let df = ((1usize << buckets.bits) - 1) as f64;
+                (buckets.normalized_chi_squared(), 1. + 5. * (2. / df).sqrt())
+            })
+            .max_by(|a, b| (a.0 - a.1).total_cmp(&(b.0 - b.1)));
+
+        let keys = hashes.len() as f64;
+        let buckets_32 = 2f64.powi(32);
+
+        TestResult {
+            name,
+            keys: hashes.len(),
+            collisions: collisions(hashes.iter().copied()),
+            collisions_32: collisions(hashes.iter().map(|hash| hash & u64::from(u32::MAX))),
+            expected_32: keys - buckets_32 * (1. - (1. - 1. / buckets_32).powf(keys)),
+            worst_chi_squared: worst,
+        }
+    }
+
+    /// a test passes if no full hashes collide, the low 32 bits collide at most about twice as
+    /// often as they would for a random function, and no table size is far from uniform
+    pub fn passed(&self) -> bool {
+        self.collisions == 0
+            && self.collisions_32 as f64 <= 2. * self.expected_32 + 2.
+            && self
+                .worst_chi_squared
+                .is_none_or(|(chi, threshold)| chi <= threshold)
+    }
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(TestResult::passed)
+    }
+}
+
+fn collisions(hashes: impl Iterator<Item = u64>) -> usize {
+    let mut seen = HashSet::new();
+    hashes.filter(|hash| !seen.insert(*hash)).count()
+}
+
+fn keyset<H: Hash>(name: String, hasher: &H, init: u64, keys: &[Vec<u8>]) -> TestResult {
+    let hashes: Vec<_> = keys
+        .iter()
+        .map(|key| hasher.hash_bytes(init, key))
+        .collect();
+    TestResult::measure(name, &hashes)
+}
+
+/// run every test of the battery on a hasher
+pub fn run<H: Hash, R: Rng>(hasher: &H, init: u64, rng: &mut R) -> Report {
+    let mut results = vec![
+        keyset(
+            "Sparse 8 bytes, <= 3 bits".into(),
+            hasher,
+            init,
+            &sparse(8, 3),
+        ),
+        keyset(
+            "Sparse 16 bytes, <= 2 bits".into(),
+            hasher,
+            init,
+            &sparse(16, 2),
+        ),
+        keyset(
+            "Permutation of 8 blocks".into(),
+            hasher,
+            init,
+            &permutation(),
+        ),
+    ];
+
+    for cycle in [4, 8] {
+        let name = format!("Cyclic {} bytes x 8", cycle);
+        results.push(keyset(name, hasher, init, &cyclic(cycle, 8, 10_000, rng)));
+    }
+
+    results.push(keyset(
+        "TwoBytes 2-4 bytes".into(),
+        hasher,
+        init,
+        &two_bytes(4),
+    ));
+    results.push(keyset(
+        "Zeroes 0-4096 bytes".into(),
+        hasher,
+        init,
+        &zeroes(4096),
+    ));
+    results.push(keyset("Text FooXXXBar".into(), hasher, init, &text()));
+    results.push(seed(hasher, 100_000, rng));
+    results.push(differential(hasher, init, 1000, rng));
+
+    Report { results }
+}
+
+/// every key of `len` bytes with at most `bits` bits set
+fn sparse(len: usize, bits: usize) -> Vec<Vec<u8>> {
+    fn extend(key: &mut Vec<u8>, from: usize, bits: usize, keys: &mut Vec<Vec<u8>>) {
+        keys.push(key.clone());
+        if bits == 0 {
+            return;
+        }
+
+        for bit in from..key.len() * 8 {
+            key[bit / 8] ^= 1 << (bit % 8);
+            extend(key, bit + 1, bits - 1, keys);
+            key[bit / 8] ^= 1 << (bit % 8);
+        }
+    }
+
+    let mut keys = Vec::new();
+    extend(&mut vec![0; len], 0, bits, &mut keys);
+    keys
+}
+
+/// every sequence of up to 5 blocks, chosen from 8 four byte blocks with few or many bits set
+fn permutation() -> Vec<Vec<u8>> {
+    const BLOCKS: [u32; 8] = [
+        0,
+        1,
+        2,
+        0x8000_0000,
+        0x8000_0001,
+        0x7fff_ffff,
+        0xffff_fffe,
+        0xffff_ffff,
+    ];
+
+    let mut keys = vec![Vec::new()];
+    let mut last = vec![Vec::new()];
+
+    for _ in 0..5 {
+        last = last
+            .iter()
+            .flat_map(|key| {
+                BLOCKS.iter().map(move |block| {
+                    let mut key = key.clone();
+                    key.extend(block.to_le_bytes());
+                    key
+                })
+            })
+            .collect();
+        keys.extend(last.iter().cloned());
+    }
+
+    keys
+}
+
+/// random `cycle` byte sequences repeated `repeats` times
+fn cyclic<R: Rng>(cycle: usize, repeats: usize, count: usize, rng: &mut R) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|_| {
+            let block: Vec<u8> = (0..cycle).map(|_| rng.gen()).collect();
+            block.repeat(repeats)
+        })
+        .collect()
+}
+
+/// every key of 2 to `max_len` bytes with at most two non-zero bytes
+fn two_bytes(max_len: usize) -> Vec<Vec<u8>> {
+    let mut keys = Vec::new();
+
+    for len in 2..=max_len {
+        keys.push(vec![0; len]);
+
+        for a in 0..len {
+            for x in 1..=u8::MAX {
+                let mut key = vec![0; len];
+                key[a] = x;
+                keys.push(key.clone());
+
+                for b in a + 1..len {
+                    for y in 1..=u8::MAX {
+                        key[b] = y;
+                        keys.push(key.clone());
+                    }
+                    key[b] = 0;
+                }
+            }
+        }
+    }
+
+    keys
+}
+
+/// all zero keys of every length up to `max_len`
+fn zeroes(max_len: usize) -> Vec<Vec<u8>> {
+    (0..=max_len).map(|len| vec![0; len]).collect()
+}
+
+/// every key `FooXXXBar` where each X is a letter or digit
+fn text() -> Vec<Vec<u8>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+    let mut keys = Vec::new();
+    for a in ALPHABET {
+        for b in ALPHABET {
+            for c in ALPHABET {
+                keys.push([b"Foo", &[*a, *b, *c][..], b"Bar"].concat());
+            }
+        }
+    }
+    keys
+}
+
+/// the same key hashed with many different initial states
+fn seed<H: Hash, R: Rng>(hasher: &H, count: usize, rng: &mut R) -> TestResult {
+    const KEY: &[u8] = b"the quick brown fox jumps over the lazy dog";
+
+    let seeds: HashSet<u64> = (0..count).map(|_| rng.gen()).collect();
+    let hashes: Vec<_> = seeds
+        .iter()
+        .map(|seed| hasher.hash_bytes(*seed, KEY))
+        .collect();
+
+    TestResult::measure(format!("Seed {} states", hashes.len()), &hashes)
+}
+
+/// random 8 byte keys, each compared to itself with every one and two bit difference applied, where
+/// a collision is a difference that doesn't change the hash
+fn differential<H: Hash, R: Rng>(hasher: &H, init: u64, count: usize, rng: &mut R) -> TestResult {
+    let diffs: Vec<u64> = (0..64)
+        .flat_map(|a| (a..64).map(move |b| (1u64 << a) | (1 << b)))
+        .collect();
+
+    let mut result = TestResult {
+        name: format!("Differential {} keys, <= 2 bits", count),
+        keys: count * diffs.len(),
+        collisions: 0,
+        collisions_32: 0,
+        expected_32: (count * diffs.len()) as f64 / 2f64.powi(32),
+        worst_chi_squared: None,
+    };
+
+    for _ in 0..count {
+        let key: u64 = rng.gen();
+        let hash = hasher.hash_bytes(init, &key.to_le_bytes());
+
+        for diff in &diffs {
+            let diff_hash = hash ^ hasher.hash_bytes(init, &(key ^ diff).to_le_bytes());
+            result.collisions += usize::from(diff_hash == 0);
+            result.collisions_32 += usize::from(diff_hash as u32 == 0);
+        }
+    }
+
+    result
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<36}{:>10}{:>12}{:>22}{:>18}  result",
+            "test", "keys", "collisions", "32 bit (expected)", "worst chi^2/df"
+        )?;
+
+        for result in &self.results {
+            let chi = match result.worst_chi_squared {
+                Some((chi, threshold)) => format!("{:.3} / {:.3}", chi, threshold),
+                None => "-".to_string(),
+            };
+
+            writeln!(
+                f,
+                "{:<36}{:>10}{:>12}{:>22}{:>18}  {}",
+                result.name,
+                result.keys,
+                result.collisions,
+                format!("{} ({:.1})", result.collisions_32, result.expected_32),
+                chi,
+                if result.passed() { "pass" } else { "FAIL" }
+            )?;
+        }
+
+        write!(
+            f,
+            "{} of {} tests passed",
+            self.results.iter().filter(|result| result.passed()).count(),
+            self.results.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hash::test::splitmix;
+
+    #[test]
+    fn strong_hash_passes() {
+        let hasher = splitmix as fn(u64, u64) -> u64;
+        let report = run(&hasher, 0, &mut thread_rng());
+
+        assert!(report.passed(), "{}", report);
+    }
+}
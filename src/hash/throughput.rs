@@ -0,0 +1,158 @@
+use super::Hash;
+use rand::prelude::*;
+use std::fmt;
+use std::hint::black_box;
+use std::time::Instant;
+
+/// the number of untimed runs before sampling, to warm up the caches and branch predictors
+const WARMUP: usize = 16;
+/// samples further than this many interquartile ranges outside the middle half are dropped
+const FENCE: f64 = 1.5;
+
+/// how fast a hasher runs over a buffer, in cycles per byte
+pub struct Throughput {
+    /// the number of bytes hashed in each sample
+    pub bytes: usize,
+    /// the number of samples kept after outlier rejection
+    pub samples: usize,
+    /// the number of samples dropped as outliers
+    pub rejected: usize,
+    pub cycles_per_byte: f64,
+    /// the variance of the cycles per byte between the kept samples
+    pub variance: f64,
+    pub nanos_per_byte: f64,
+}
+
+impl Throughput {
+    /// time hashing a random buffer of `bytes` bytes `samples` times after a warmup, and drop
+    /// the samples outside the interquartile fences (interrupts, page faults and frequency
+    /// changes only ever make a sample slower)
+    pub fn measure<H: Hash, R: Rng>(
+        hasher: &H,
+        init: u64,
+        bytes: usize,
+        samples: usize,
+        rng: &mut R,
+    ) -> Throughput {
+        let mut buffer = vec![0u8; bytes];
+        rng.fill_bytes(&mut buffer);
+
+        for _ in 0..WARMUP {
+            black_box(hasher.hash_bytes(black_box(init), black_box(&buffer)));
+        }
+
+        let mut cycles = Vec::with_capacity(samples);
+        let start = Instant::now();
+        for _ in 0..samples {
+            let before = ticks();
+            black_box(hasher.hash_bytes(black_box(init), black_box(&buffer)));
+            cycles.push((ticks() - before) as f64 / bytes as f64);
+        }
+        let nanos_per_byte = start.elapsed().as_nanos() as f64 / (samples * bytes) as f64;
+
+        let rejected = reject_outliers(&mut cycles);
+        let mean = cycles.iter().sum::<f64>() / cycles.len() as f64;
+        let variance =
+            cycles.iter().map(|c| (c - mean) * (c - mean)).sum::<f64>() / cycles.len() as f64;
+
+        Throughput {
+            bytes,
+            samples: cycles.len(),
+            rejected,
+            cycles_per_byte: mean,
+            variance,
+            nanos_per_byte,
+        }
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+/// the time stamp counter, which counts at a constant rate close to the nominal clock speed
+#[cfg(target_arch = "x86_64")]
+fn ticks() -> u64 {
+    unsafe { std::arch::x86_64::_rdtsc() }
+}
+
+/// nanoseconds since the first call, on targets without a time stamp counter
+#[cfg(not(target_arch = "x86_64"))]
+fn ticks() -> u64 {
+    use std::sync::OnceLock;
+
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+/// sort the samples and drop the ones outside the tukey fences, returning how many were dropped
+fn reject_outliers(samples: &mut Vec<f64>) -> usize {
+    samples.sort_by(f64::total_cmp);
+
+    let len = samples.len();
+    let (q1, q3) = (samples[len / 4], samples[len * 3 / 4]);
+    let (low, high) = (q1 - FENCE * (q3 - q1), q3 + FENCE * (q3 - q1));
+
+    samples.retain(|sample| (low..=high).contains(sample));
+    len - samples.len()
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3} cycles/byte (std dev {:.3}), {:.3} ns/byte over {} samples of {} bytes ({} rejected)",
+            self.cycles_per_byte,
+            self.std_dev(),
+            self.nanos_per_byte,
+            self.samples,
+            self.bytes,
+            self.rejected
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rejects_slow_outliers() {
+        let mut samples: Vec<f64> = (0..100).map(|i| 2. + (i % 10) as f64 * 0.01).collect();
+        samples.extend([40., 1000.]);
+
+        assert_eq!(reject_outliers(&mut samples), 2);
+        assert!(samples.iter().all(|sample| *sample < 3.));
+    }
+
+    #[test]
+    fn longer_chains_are_slower() {
+        let fast: fn(u64, u64) -> u64 = |state, byte| state ^ byte;
+        let slow: fn(u64, u64) -> u64 = |state, byte| {
+            (0..64).fold(state, |state, i| {
+                black_box(state.rotate_left(i).wrapping_add(byte))
+            })
+        };
+        let mut rng = thread_rng();
+
+        // the median of a few runs, so a run slowed down by the rest of the machine doesn't count
+        let mut median = |hasher: &fn(u64, u64) -> u64| {
+            let mut runs: Vec<_> = (0..5)
+                .map(|_| Throughput::measure(hasher, 0, 4096, 50, &mut rng))
+                .collect();
+            assert!(runs.iter().all(|run| run.variance >= 0.));
+            runs.sort_by(|a, b| a.cycles_per_byte.total_cmp(&b.cycles_per_byte));
+            runs.swap_remove(2)
+        };
+        let fast = median(&fast);
+        let slow = median(&slow);
+
+        // the slow hasher does 64 dependent steps per byte to the fast one's 1
+        assert!(
+            slow.cycles_per_byte > 4. * fast.cycles_per_byte,
+            "{}\n{}",
+            fast,
+            slow
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::bytecode::code::{Instruction, Program, Value};
+use crate::bytecode::verify::{self, Target};
 use crate::hash::Hash;
 use crate::jit::asm::Assembler;
 use std::fs::write;
@@ -18,7 +19,7 @@ pub struct CodeGuard {
 }
 
 impl CodeGuard {
-    fn call(&self, state: u64, byte: u64) -> u64 {
+    pub fn call(&self, state: u64, byte: u64) -> u64 {
         (self.func)(state, byte)
     }
 }
@@ -44,16 +45,18 @@ where
     A: Assembler + Default,
     A::Memory: From<usize>,
 {
-    pub fn jit_prog(prog: &Program) -> CodeGuard {
+    pub fn jit_prog(prog: &Program) -> verify::Result<CodeGuard> {
+        prog.verify(Target::Jit)?;
+
         let mut asm = A::default();
         Jit::asm_prog(&mut asm, prog);
 
         let (buffer, _, finalizer) = asm.finalize();
 
-        CodeGuard {
+        Ok(CodeGuard {
             func: unsafe { transmute(buffer) },
             finalizer,
-        }
+        })
     }
 
     pub fn objdump_prog(prog: &Program) -> io::Result<String> {
@@ -83,6 +86,7 @@ where
         Ok(String::from_utf8_lossy(&child.stdout).to_string())
     }
 
+    /// assemble a program without verifying it first, see `Program::verify`
     pub fn asm_prog(asm: &mut A, prog: &Program) {
         for instr in prog.instructions.iter().copied() {
             match instr {
@@ -10,21 +10,65 @@ mod expr;
 mod hash;
 mod jit;
 mod jit_prog;
+mod sat;
 mod search;
+mod seed;
+mod ssa;
 
-use bytecode::code::{Instruction, Program, Value};
-use bytecode::gen::emit;
+use bytecode::code::Program;
+use bytecode::vm::Vm;
+use expr::analysis::degeneracy;
+use expr::bijective::{self, Bijectivity};
 use expr::closure::*;
 use expr::expr::{Expr, Tag};
-use expr::parse::parse;
-use hash::{score_hasher, Hash};
-use jit::{asm::*, code_vec::CodeVec, linux::*};
+use expr::inverse;
+use expr::linear::{self, Linearity};
+use expr::parse::{parse, parse_shape};
+use hash::avalanche::Avalanche;
+use hash::bic::Bic;
+use hash::distribution::Distribution;
+use hash::keys::KeySet;
+use hash::{keys, smhasher, Hash, Metric};
+use jit::linux::*;
 use jit_prog::Jit;
 use rand::prelude::*;
+use sat::Equivalence;
 use search::bfs::Search;
+use search::cegis::{Cegis, Spec};
+use search::eval::{Evaluator, Objective};
+use search::genetic::Genetic;
+use search::pareto::Front;
+use search::staged::Staged;
+use search::superopt::{self, Target};
 use search::tag::Tagger;
-use std::mem::transmute;
-use std::time::{Duration, Instant};
+use search::top::Top;
+use search::tune::{Tuned, Tuner};
+use ssa::code::Function;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// the number of keys generated for the key set metrics
+const KEYS: usize = 4096;
+/// the seed the key set metrics are generated from
+const KEY_SEED: u64 = 0;
+/// the length of the keys the bit independence metric is measured on
+const BIC_KEY_LEN: usize = 8;
+/// the number of keys the bit independence metric is measured on
+const BIC_SAMPLES: usize = 100;
+/// the length of the keys the avalanche report of `best-hash smhasher` is measured on
+const AVALANCHE_KEY_LEN: usize = 8;
+/// the number of keys the avalanche report is measured on, as many as smhasher's own avalanche
+/// test
+const AVALANCHE_SAMPLES: usize = 300_000;
+/// the number of keys of `BIC_KEY_LEN` bytes the bit independence report of `best-hash smhasher`
+/// is measured on, enough for a strong hash to stay under a correlation of 0.05
+const BIC_REPORT_SAMPLES: usize = 10_000;
+/// the number of random states hashed when looking for collisions in the state update
+const BIJECTIVITY_SAMPLES: usize = 1 << 16;
+/// the number of random states and bytes an inverse is checked on
+const ROUND_TRIPS: usize = 1 << 16;
+/// the index of the seed a candidate is scored with, derived from the seed it was tagged with
+const SCORE_STREAM: u64 = u64::MAX;
 
 fn format_micros(time: f64) -> String {
     let micros = time % 1000.;
@@ -35,60 +79,666 @@ fn format_micros(time: f64) -> String {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("smhasher") => vet(&args[2..]),
+        Some("score") => score(&args[2..]),
+        Some("invert") => invert(&args[2..]),
+        Some("equiv") => equiv(&args[2..]),
+        Some("superopt") => superopt(&args[2..]),
+        Some("cegis") => synthesize(&args[2..]),
+        Some("genetic") => evolve(&args[2..]),
+        Some("tune") => tune(&args[2..]),
+        _ => run_search(&args),
+    }
+}
+
+/// the objective given by `--objective <quality|speed|combined:weight>`, quality by default
+fn objective(args: &[String]) -> Objective {
+    option(args, "--objective").map_or(Objective::Quality, |objective| {
+        objective.parse().unwrap_or_else(|err| panic!("{}", err))
+    })
+}
+
+/// find the value of a `--name value` option
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .skip_while(|arg| *arg != name)
+        .nth(1)
+        .map(String::as_str)
+}
+
+/// the seed given by `--seed <seed>`, or a random one, which is printed so the run can be repeated
+fn master_seed(args: &[String]) -> u64 {
+    let seed = option(args, "--seed").map_or_else(
+        || thread_rng().gen(),
+        |seed| seed.parse().expect("expected the seed to be a number"),
+    );
+    println!("using seed {}", seed);
+    seed
+}
+
+/// the keys given by `--keys <key set or corpus file>`
+fn keys(args: &[String]) -> Option<Rc<[Vec<u8>]>> {
+    option(args, "--keys").map(|spec| {
+        // the keys don't depend on the seed of a run, so scores stay comparable between runs
+        keys::from_spec(spec, KEYS, &mut seed::rng(KEY_SEED))
+            .unwrap_or_else(|err| panic!("{}", err))
+            .into()
+    })
+}
+
+/// the extra score terms, including the key set metrics if keys are given and the bit
+/// independence criterion if it is given a weight with `--bic <weight>`
+fn metrics(args: &[String]) -> Vec<Metric> {
+    let mut metrics = Vec::new();
+
+    if let Some(keys) = keys(args) {
+        metrics.push(Metric::Distribution {
+            keys: keys.clone(),
+            weight: 4.,
+        });
+        metrics.push(Metric::KeyAvalanche {
+            keys,
+            mutations: 1,
+            weight: 4.,
+        });
+    }
+    if let Some(weight) = option(args, "--bic") {
+        metrics.push(Metric::Bic {
+            key_len: BIC_KEY_LEN,
+            samples: BIC_SAMPLES,
+            weight: weight
+                .parse()
+                .expect("expected the bic weight to be a number"),
+        });
+    }
+
+    metrics
+}
+
+fn parse_expr(text: &str) -> Expr<Tag> {
+    parse(text).unwrap_or_else(|err| panic!("couldn't parse {}: {:?}", text, err))
+}
+
+/// print why an expression can't be a good hash, whether its state update is a permutation and
+/// the parts of it that are affine over GF(2)
+fn explain<R: Rng>(expr: &Expr<Tag>, rng: &mut R) {
+    if let Some(degeneracy) = degeneracy(expr) {
+        println!("degenerate: {}", degeneracy);
+    }
+    println!(
+        "the state update is {}",
+        bijective::classify_sampled(expr, BIJECTIVITY_SAMPLES, rng)
+    );
+
+    for part in linear::affine_parts(expr) {
+        if let Some(linearity) = Linearity::of(part) {
+            println!("{}\n{}", part, linearity);
+        }
+    }
+}
+
+/// run the smhasher battery and the avalanche, bit independence and distribution reports on an
+/// expression, with `best-hash smhasher <expr> [backend]` where the backend is one of `jit` (the
+/// default), `vm`, `closure` or `expr`, exiting with an error code if any test fails, and the
+/// distribution is measured over `--keys` or sequential integers
+fn vet(args: &[String]) {
+    let expr = parse_expr(args.first().expect("expected an expression to test"));
+    let prog = ssa::gen::emit(&Function::from(&expr).optimize());
+    let mut rng = seed::rng(master_seed(args));
+
+    let equivalence = sat::equivalent(&expr, &prog);
+    assert_eq!(
+        equivalence,
+        Equivalence::Equivalent,
+        "{} and its optimized program are {}",
+        expr,
+        equivalence
+    );
+
+    let backend = args.get(1).filter(|arg| !arg.starts_with("--"));
+    let keys = keys(args).unwrap_or_else(|| {
+        KeySet::Sequential { len: 8 }
+            .generate(KEYS, &mut seed::rng(KEY_SEED))
+            .into()
+    });
+
+    println!("{}", expr);
+
+    let passed = match backend.map_or("jit", String::as_str) {
+        "jit" => battery(
+            &Jit::<Linux_x86_64>::jit_prog(&prog).unwrap(),
+            &keys,
+            &mut rng,
+        ),
+        "vm" => battery(&Vm::new(&prog).unwrap(), &keys, &mut rng),
+        "closure" => battery(&Hasher::from(&expr), &keys, &mut rng),
+        "expr" => battery(&expr, &keys, &mut rng),
+        backend => panic!("unknown backend {}", backend),
+    };
+
+    explain(&expr, &mut rng);
+
+    if !passed {
+        std::process::exit(1);
+    }
+}
+
+/// print the smhasher battery and the avalanche, bit independence and distribution reports of a
+/// hasher, and return whether the battery and the avalanche passed, the others have no threshold
+fn battery<H: Hash, R: Rng>(hasher: &H, keys: &[Vec<u8>], rng: &mut R) -> bool {
+    let report = smhasher::run(hasher, 0, rng);
+    println!("{}", report);
+
+    let avalanche = Avalanche::measure(hasher, 0, AVALANCHE_KEY_LEN, AVALANCHE_SAMPLES, rng);
+    println!("{}", avalanche);
+
+    let bic = Bic::measure(hasher, 0, BIC_KEY_LEN, BIC_REPORT_SAMPLES, rng);
+    println!("{}", bic);
+
+    let distribution = Distribution::measure(hasher, 0, keys.iter().map(Vec::as_slice));
+    println!("{}", distribution);
+
+    report.passed() && avalanche.passes()
+}
+
+/// rescore a candidate from a search, with `best-hash score <expr> <seed>` and the same
+/// `--keys` and `--objective` as the search
+fn score(args: &[String]) {
+    let expr = parse_expr(args.first().expect("expected an expression to score"));
+    let seed = args
+        .get(1)
+        .and_then(|seed| seed.parse().ok())
+        .expect("expected the seed the candidate was scored with");
+
+    let seed = seed::derive(seed, SCORE_STREAM);
+    let mut evaluator = Evaluator::new(metrics(args), objective(args));
+
+    let quality = evaluator.quality(&expr, seed);
+    let throughput = evaluator.throughput(&expr, seed);
+    let score = evaluator.score(&expr, seed);
+
+    println!(
+        "{}\n\thas quality {}\n\truns at {}\n\thas {} score {}",
+        expr, quality, throughput, evaluator.objective, score
+    );
+    explain(&expr, &mut seed::rng(seed));
+}
+
+/// prove that two expressions compute the same function, with `best-hash equiv <expr> <expr>`,
+/// or find a state and byte they differ on
+fn equiv(args: &[String]) {
+    let [a, b] =
+        [0, 1].map(|idx| parse_expr(args.get(idx).expect("expected two expressions to compare")));
+
+    println!("{}\n{}\n\tare {}", a, b, sat::equivalent(&a, &b));
+}
+
+/// find the smallest expression equivalent to an expression or a bytecode program, with
+/// `best-hash superopt <expr|path> [--leaves n] [--budget n]`, trying at most `--budget` candidates
+/// (10 million by default) of up to `--leaves` leaves (one fewer than the expression, or 4 for a
+/// program)
+fn superopt(args: &[String]) {
+    let target = args
+        .first()
+        .expect("expected an expression or a program to superoptimize");
+    let budget = option(args, "--budget").map_or(10_000_000, |budget| {
+        budget
+            .parse()
+            .expect("expected the budget to be a number of candidates")
+    });
+    let leaves = option(args, "--leaves").map(|leaves| {
+        leaves
+            .parse()
+            .expect("expected the number of leaves to be a number")
+    });
+
+    match std::fs::read_to_string(target) {
+        Ok(text) => {
+            let prog: Program = text
+                .parse()
+                .unwrap_or_else(|err| panic!("couldn't parse {}: {}", target, err));
+            print!("{}", prog);
+            report_superopt(&prog, leaves.unwrap_or(4), budget);
+        }
+        Err(_) => {
+            let expr = parse_expr(target);
+            println!("{} has {} leaves", expr, expr.len());
+            report_superopt(&expr, leaves.unwrap_or(expr.len() - 1), budget);
+        }
+    }
+}
+
+fn report_superopt<T: Target>(target: &T, leaves: usize, budget: usize) {
+    let start = Instant::now();
+    let report = superopt::superoptimize(target, leaves, budget);
+
+    match report.found {
+        Some(expr) => println!("is equivalent to {} with {} leaves", expr, expr.len()),
+        None if report.candidates == budget => {
+            println!("no equivalent expression was found within the budget")
+        }
+        None => println!("has no equivalent expression of up to {} leaves", leaves),
+    }
+    println!(
+        "{} candidates tried and {} refuted by the SAT solver in {}",
+        report.candidates,
+        report.refuted,
+        format_micros(start.elapsed().as_secs_f64() * 1e6)
+    );
+}
+
+/// synthesize the tags of a shape from a specification, with `best-hash cegis <shape>` and any of
+/// `--vector <init>,<hex bytes>,<hash>` (repeated for each test vector), `--depends <mask>` for
+/// output bits that must depend on every input bit, `--matches <expr>` and `--iterations n`
+fn synthesize(args: &[String]) {
+    let text = args.first().expect("expected a shape like ((_ + _) << _)");
+    let shape =
+        parse_shape(text).unwrap_or_else(|err| panic!("couldn't parse {}: {:?}", text, err));
+    let iterations = option(args, "--iterations").map_or(1000, |iterations| {
+        iterations
+            .parse()
+            .expect("expected the number of iterations to be a number")
+    });
+
+    let mut specs: Vec<Spec> = args
+        .windows(2)
+        .filter(|pair| pair[0] == "--vector")
+        .map(|pair| {
+            let parts: Vec<_> = pair[1].split(',').collect();
+            let [init, bytes, hash] = parts[..] else {
+                panic!(
+                    "expected a vector as <init>,<hex bytes>,<hash>, got {}",
+                    pair[1]
+                );
+            };
+            let bytes = (0..bytes.len())
+                .step_by(2)
+                .map(|idx| u8::from_str_radix(&bytes[idx..idx + 2], 16))
+                .collect::<Result<_, _>>()
+                .expect("expected the bytes of a vector in hex");
+            Spec::Vector {
+                init: init
+                    .parse()
+                    .expect("expected the initial state to be a number"),
+                bytes,
+                hash: hash.parse().expect("expected the hash to be a number"),
+            }
+        })
+        .collect();
+    if let Some(mask) = option(args, "--depends") {
+        let outputs = u64::from_str_radix(mask.trim_start_matches("0x"), 16)
+            .expect("expected the output bits as a hex mask");
+        specs.push(Spec::Depends { outputs });
+    }
+    if let Some(expr) = option(args, "--matches") {
+        specs.push(Spec::Matches(parse_expr(expr)));
+    }
+
+    for spec in &specs {
+        println!("{}", spec);
+    }
+
+    let start = Instant::now();
+    let mut cegis = Cegis::new(&shape, &specs);
+    let result = cegis.synthesize(iterations, &mut seed::rng(master_seed(args)));
+    match result {
+        Ok(expr) => println!("{} meets the specification", expr),
+        Err(failure) => println!("{}: {}", shape, failure),
+    }
+    println!(
+        "after {} candidates in {}",
+        cegis.iterations,
+        format_micros(start.elapsed().as_secs_f64() * 1e6)
+    );
+}
+
+/// derive the inverse of a state update, with `best-hash invert <expr> [hash]`, check it round
+/// trips and print keys with the initial states that hash them to `hash`, 0 by default
+fn invert(args: &[String]) {
+    let expr = parse_expr(args.first().expect("expected an expression to invert"));
+    let hash = args
+        .get(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map_or(0, |hash| {
+            hash.parse().expect("expected the hash to be a number")
+        });
+    let mut rng = seed::rng(master_seed(args));
+
+    let Some(inverse) = inverse::invert(&expr) else {
+        println!(
+            "{} has no inverse, the state update is {}",
+            expr,
+            bijective::classify_sampled(&expr, BIJECTIVITY_SAMPLES, &mut rng)
+        );
+        return;
+    };
+    println!("{}\n\tis inverted by {}", expr, inverse);
+
+    if let Err((state, byte)) = inverse::round_trip(&expr, &inverse, ROUND_TRIPS, &mut rng) {
+        panic!(
+            "the inverse doesn't undo state {:#x} and byte {}",
+            state, byte
+        );
+    }
+    println!("\tand undoes {} random states and bytes", ROUND_TRIPS);
+
+    for len in [1, 4, 8, 16] {
+        let key: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+        let init = inverse::unhash(&inverse, hash, &key);
+        assert_eq!(expr.hash_bytes(init, &key), hash);
+        println!(
+            "the initial state {:#018x} hashes {:02x?} to {}",
+            init, key, hash
+        );
+    }
+}
+
+/// with `--bijective proven` only taggings with a state update that is provably a permutation
+/// are kept, and with `--bijective possible` ones that provably aren't are dropped
+fn bijective_filter(args: &[String]) -> Option<bool> {
+    option(args, "--bijective").map(|filter| match filter {
+        "proven" => true,
+        "possible" => false,
+        filter => panic!("unknown filter {}, expected proven or possible", filter),
+    })
+}
+
+/// whether a tagging is neither degenerate nor dropped by the `--bijective` filter
+fn worth_scoring(tagged: &Expr<Tag>, proven_bijective: Option<bool>) -> bool {
+    degeneracy(tagged).is_none()
+        && matches!(
+            (proven_bijective, bijective::classify(tagged)),
+            (None, _) | (_, Bijectivity::Bijective) | (Some(false), Bijectivity::Unknown)
+        )
+}
+
+/// evolve tagged expressions with `best-hash genetic`, scoring them the same way as the search,
+/// with `--generations`, `--population` and `--leaves` for the most leaves an expression can have
+fn evolve(args: &[String]) {
+    let number = |name: &str, default: usize| {
+        option(args, name).map_or(default, |num| {
+            num.parse()
+                .unwrap_or_else(|_| panic!("expected {} to be a number", name))
+        })
+    };
+    let generations = number("--generations", 50);
+    let leaves = number("--leaves", 12);
+    assert!(leaves >= 2, "expected --leaves to be at least 2");
+    let mut genetic = Genetic::new(master_seed(args), number("--population", 200), leaves);
+    let proven_bijective = bijective_filter(args);
+    let mut evaluator = Evaluator::new(metrics(args), objective(args));
+
+    for generation in 0..=generations {
+        genetic.step(|tagged, candidate| {
+            worth_scoring(tagged, proven_bijective)
+                .then(|| evaluator.score(tagged, seed::derive(candidate, SCORE_STREAM)))
+        });
+
+        let scored: Vec<_> = genetic
+            .population()
+            .iter()
+            .filter_map(|ind| Some((ind.score?, ind.expr.len())))
+            .collect();
+        let mean = |f: fn(&(f64, usize)) -> f64| {
+            scored.iter().map(f).sum::<f64>() / scored.len().max(1) as f64
+        };
+        println!(
+            "generation {}: best score {}, mean score {}, mean leaves {:.1}",
+            generation,
+            genetic
+                .best()
+                .and_then(|best| best.score)
+                .unwrap_or(f64::NAN),
+            mean(|(score, _)| *score),
+            mean(|(_, len)| *len as f64)
+        );
+    }
+    println!("{}", genetic.stats);
+
+    // the candidates can be rescored with `best-hash score "<expr>" <seed>`
+    for ind in genetic.population().iter().take(5) {
+        if let Some(score) = ind.score {
+            println!("{}\n\thas score {} (seed {})\n", ind.expr, score, ind.seed);
+        }
+    }
+}
+
+/// tune the constants of a candidate scored with `seed`, on the inputs it was scored on
+fn tune_constants(
+    evaluator: &mut Evaluator,
+    expr: &Expr<Tag>,
+    seed: u64,
+    iterations: usize,
+) -> Tuned {
+    let score_seed = seed::derive(seed, SCORE_STREAM);
+    Tuner::new(iterations).tune(expr, &mut seed::rng(seed), |tuned| {
+        evaluator.score(tuned, score_seed)
+    })
+}
+
+/// tune the constants of an expression with `best-hash tune "<expr>" <seed>`, taking the seed it
+/// was scored with (a random one by default), `--iterations` and the options of `score`
+fn tune(args: &[String]) {
+    let expr = parse_expr(args.first().expect("expected an expression to tune"));
+    let seed = args
+        .get(1)
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(|| master_seed(args));
+    let iterations = option(args, "--iterations").map_or(2000, |iterations| {
+        iterations
+            .parse()
+            .expect("expected the number of iterations to be a number")
+    });
+    let mut evaluator = Evaluator::new(metrics(args), objective(args));
+
+    let tuned = tune_constants(&mut evaluator, &expr, seed, iterations);
+    for step in tuned.trace.windows(2).filter(|w| w[1].best > w[0].best) {
+        println!(
+            "iteration {}: best score {}",
+            step[1].iteration, step[1].best
+        );
+    }
+
+    // the constants are tuned on the inputs of one seed, so also compare them on fresh ones
+    let fresh = seed::derive(seed::derive(seed, SCORE_STREAM), 0);
+    println!(
+        "{}\n\thas score {}, and {} on fresh inputs\ntuned to\n{}\n\thas score {} (seed {}), and {} on fresh inputs",
+        expr,
+        tuned.trace[0].score,
+        evaluator.score(&expr, fresh),
+        tuned.expr,
+        tuned.score,
+        seed,
+        evaluator.score(&tuned.expr, fresh)
+    );
+}
+
+fn run_search(args: &[String]) {
     // calling search.next() n times, search.to_visit will contain 3n + 1 elements
     let search = Search::default();
-    let tagger = Tagger::default();
-    let mut rng = thread_rng();
-    let mut code = CodeVec::default();
-
+    // the tagger learns from the score of every tagging which tags to favour where, `--exploration
+    // <x>` keeps its odds closer to the fixed prior the larger `x` is, and `inf` turns it off
+    let mut tagger = Tagger::default();
+    if let Some(exploration) = option(args, "--exploration") {
+        tagger.exploration = exploration
+            .parse()
+            .expect("expected the exploration to be a number");
+    }
+    let master = master_seed(args);
+    let shapes = option(args, "--shapes").map_or(100_000, |shapes| {
+        shapes
+            .parse()
+            .expect("expected the number of shapes to be a number")
+    });
+    let mut evaluator = Evaluator::new(metrics(args), objective(args));
+
+    // with `--front <path>`, every tagging is also measured on each objective separately, and
+    // the ones no other tagging beats on every objective are written to `path`
+    let front_path = option(args, "--front");
+    let front_keys = keys(args).unwrap_or_else(|| {
+        KeySet::Sequential { len: 8 }
+            .generate(KEYS, &mut seed::rng(KEY_SEED))
+            .into()
+    });
+    let mut front = Front::default();
+
+    // every shape that survives is kept with a summary of the scores of its taggings and its best
+    // tagging, and the best taggings of the whole run are kept too, `--top <k>` of them
     let mut scored_exprs = Vec::new();
+    let mut top = Top::new(option(args, "--top").map_or(10, |k| {
+        k.parse()
+            .expect("expected the number of taggings to keep to be a number")
+    }));
+    let stats_path = option(args, "--stats");
+
+    let proven_bijective = bijective_filter(args);
+
+    // shapes are scored on 100 taggings, unless the first few show they can't reach the top 5
+    let mut staged = Staged::new(5, 100);
+    if let Some(confidence) = option(args, "--confidence") {
+        staged.confidence = confidence
+            .parse()
+            .expect("expected the confidence to be a number");
+    }
 
-    for (i, expr) in search.take(100_000).enumerate() {
+    for (i, expr) in search.take(shapes).enumerate() {
         if i % 100 == 0 {
             println!("{}", i);
         }
-        let mut score = 0.;
-        for _ in 0..100 {
-            let tagged = tagger.annotate(&expr);
-            let prog = emit(&tagged, 6);
-
-            let mut asm = Linux_x86_64::new(code);
-            Jit::asm_prog(&mut asm, &prog);
-            let (buffer, _, cap) = asm.finalize_with_cap();
-            let jit: fn(u64, u64) -> u64 = unsafe { transmute(buffer) };
-
-            score += score_hasher(jit, tagged.len(), 0, 10, 3, 50, 3, &mut rng);
-            code = unsafe { CodeVec::from_raw_parts(buffer as *mut u8, 0, cap) };
+        // every tagging of a shape gets its own seed, derived from the seed of the shape
+        let shape_seed = seed::derive(master, i as u64);
+        let mut best: Option<(f64, Expr<Tag>, u64)> = None;
+        let summary = staged.score(|j| {
+            let candidate = seed::derive(shape_seed, j);
+            let tagged = tagger.annotate(&expr, &mut seed::rng(candidate));
+            // the tagger already tries to avoid degenerate ones, so don't spend any time on the rest
+            if !worth_scoring(&tagged, proven_bijective) {
+                return None;
+            }
+            let tagged_score = evaluator.score(&tagged, seed::derive(candidate, SCORE_STREAM));
+            tagger.feedback(&expr, &tagged, tagged_score);
+
+            if front_path.is_some() {
+                let objectives = evaluator.objectives(
+                    &tagged,
+                    seed::derive(candidate, SCORE_STREAM),
+                    &front_keys,
+                );
+                front.insert(objectives, candidate, tagged.clone());
+            }
+
+            if top
+                .threshold()
+                .is_none_or(|threshold| tagged_score > threshold)
+            {
+                top.insert(tagged_score, (tagged.clone(), candidate));
+            }
+            if best
+                .as_ref()
+                .is_none_or(|(best, _, _)| tagged_score > *best)
+            {
+                best = Some((tagged_score, tagged, candidate));
+            }
+            Some(tagged_score)
+        });
+
+        if let Some(summary) = summary {
+            scored_exprs.push((summary, expr, best.unwrap()));
         }
+    }
+    println!("{}", staged.stats);
+    scored_exprs.sort_by(|(a, _, _), (b, _, _)| a.total.total_cmp(&b.total));
+
+    // the best tagging of a shape can be rescored with `best-hash score "<tagging>" <seed>`, fewer
+    // than 5 shapes may have survived
+    let good = scored_exprs.len().saturating_sub(5);
+    let bottom = scored_exprs.iter().take(5);
+    for (summary, expr, (_, best, seed)) in bottom.chain(scored_exprs.iter().skip(good)) {
+        println!(
+            "{}\n\thas score {}, a tagging scores {} on average (std dev {}) and at most {} over {} taggings, the best one\n{}\n\thas seed {}\n",
+            expr,
+            summary.total,
+            summary.mean,
+            summary.variance.sqrt(),
+            summary.max,
+            summary.taggings,
+            best,
+            seed
+        );
+    }
 
-        scored_exprs.push((score, expr));
+    println!("the {} best taggings of the run", top.len());
+    for (score, (tagged, seed)) in top.iter() {
+        println!("{}\n\thas score {} (seed {})\n", tagged, score, seed);
     }
-    scored_exprs.sort_by_key(|(score, _)| (score * 100f64) as u128);
 
-    for (score, expr) in scored_exprs.iter().take(5) {
-        println!("{}\n\thas score {}\n", expr, score);
+    // with `--tune <iterations>`, the constants of the best tagging of each of the top 5 shapes
+    // are tuned too
+    if let Some(iterations) = option(args, "--tune") {
+        let iterations = iterations
+            .parse()
+            .expect("expected the number of iterations to be a number");
+        for (_, _, (_, best, seed)) in scored_exprs.iter().skip(good) {
+            let tuned = tune_constants(&mut evaluator, best, *seed, iterations);
+            println!(
+                "{}\n\ttuned to\n{}\n\thas score {} (seed {})\n",
+                best, tuned.expr, tuned.score, seed
+            );
+        }
     }
 
-    for (score, expr) in scored_exprs.iter().skip(scored_exprs.len() - 5) {
-        println!("{}\n\thas score {}\n", expr, score);
+    // with `--stats <path>`, the summary of every shape that survived is written to `path` as tab
+    // separated values
+    if let Some(path) = stats_path {
+        let mut stats = String::from("shape\ttaggings\tmean\tvariance\tmax\tbest tagging\tseed\n");
+        for (summary, expr, (_, best, seed)) in scored_exprs.iter().rev() {
+            stats += &format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                expr, summary.taggings, summary.mean, summary.variance, summary.max, best, seed
+            );
+        }
+        std::fs::write(path, stats)
+            .unwrap_or_else(|err| panic!("couldn't write {}: {}", path, err));
+        println!(
+            "wrote the statistics of {} shapes to {}",
+            scored_exprs.len(),
+            path
+        );
+    }
+
+    if let Some(path) = front_path {
+        std::fs::write(path, front.to_string())
+            .unwrap_or_else(|err| panic!("couldn't write {}: {}", path, err));
+        println!(
+            "wrote a pareto front of {} candidates to {}",
+            front.len(),
+            path
+        );
     }
 
     let len_total = scored_exprs
         .iter()
-        .map(|(_, expr)| expr.len() as f64)
+        .map(|(_, expr, _)| expr.len() as f64)
         .fold(0f64, |acc, len| acc + len);
     let len_bad = scored_exprs
         .iter()
         .take(5)
-        .map(|(_, expr)| expr.len() as f64)
+        .map(|(_, expr, _)| expr.len() as f64)
         .fold(0f64, |acc, len| acc + len);
     let len_good = scored_exprs
         .iter()
-        .skip(scored_exprs.len() - 5)
-        .map(|(_, expr)| expr.len() as f64)
+        .skip(good)
+        .map(|(_, expr, _)| expr.len() as f64)
         .fold(0f64, |acc, len| acc + len);
+    let shown = (scored_exprs.len() - good) as f64;
 
-    println!("the average length of an expression is {}, the average length of a bad expression is {}, the average length of a good expression is {}", len_total / (scored_exprs.len() as f64), len_bad / 5f64, len_good / 5f64);
+    // there are no averages to print if no shape survived
+    if shown > 0. {
+        println!("the average length of an expression is {}, the average length of a bad expression is {}, the average length of a good expression is {}", len_total / (scored_exprs.len() as f64), len_bad / shown, len_good / shown);
+    }
 }
 
 /*
@@ -117,7 +767,7 @@ fn main() {
         expr_len += expr.len();
         let closure = Hasher::from(&expr);
         let prog = emit(&expr, 6);
-        let func = Jit::<Linux_x86_64>::jit_prog(&prog);
+        let func = Jit::<Linux_x86_64>::jit_prog(&prog).unwrap();
         std::fs::write("log.txt", format!("{}\nwith bytecode\n{}\nand machine code\n{}\n", expr, prog, Jit::<Linux_x86_64>::objdump_prog(&prog).unwrap())).unwrap();
 
         let bytes: Vec<_> = (0..1000).map(|_| rng.gen()).collect();
@@ -151,6 +801,7 @@ fn main() {
 #[cfg(test)]
 mod test {
     use super::*;
+    use bytecode::gen::emit;
 
     const ITERS: usize = 1000;
     const TESTS: usize = 10;
@@ -164,12 +815,12 @@ mod test {
         'outer: for _ in 0..ITERS {
             let expr = Expr::rand(&mut rng);
             let prog = emit(&expr, 6);
-            let func = Jit::<Linux_x86_64>::jit_prog(&prog);
+            let func = Jit::<Linux_x86_64>::jit_prog(&prog).unwrap();
 
             for _ in 0..TESTS {
                 let hash_state = rng.gen();
                 let byte: u8 = rng.gen();
-                let jit_res = func(hash_state, byte as u64);
+                let jit_res = func.call(hash_state, byte as u64);
                 let eval_res = prog.eval(hash_state, byte);
 
                 if jit_res != eval_res {
@@ -189,4 +840,83 @@ mod test {
             panic!("{} out of {} expressions failed", failed.len(), ITERS);
         }
     }
+
+    #[test]
+    fn ssa_jit_eq() {
+        let mut rng = thread_rng();
+
+        let mut failed = Vec::new();
+
+        'outer: for _ in 0..ITERS {
+            let expr = Expr::rand(&mut rng);
+            let prog = ssa::gen::emit(&Function::from(&expr).optimize());
+            let func = Jit::<Linux_x86_64>::jit_prog(&prog).unwrap();
+
+            for _ in 0..TESTS {
+                let hash_state = rng.gen();
+                let byte: u8 = rng.gen();
+                let jit_res = func.call(hash_state, byte as u64);
+                let eval_res = expr.hash_bytes(hash_state, &[byte]);
+
+                if jit_res != eval_res {
+                    failed.push(expr);
+                    continue 'outer;
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            failed.sort_by_key(|e| usize::MAX - e.len());
+
+            for expr in &failed {
+                println!("\n{}", expr);
+            }
+
+            panic!("{} out of {} expressions failed", failed.len(), ITERS);
+        }
+    }
+
+    #[test]
+    fn bic_weight_changes_the_score() {
+        let args = ["--bic", "4"].map(String::from);
+        let expr = parse(
+            "(((state + byte) xor 11400714819323198485) << ((state xor byte) + (state >> 29)))",
+        )
+        .unwrap();
+        let default = Evaluator::new(metrics(&[]), Objective::Quality).score(&expr, 0);
+        let mut evaluator = Evaluator::new(metrics(&args), Objective::Quality);
+
+        assert!(matches!(evaluator.metrics[..], [Metric::Bic { .. }]));
+        assert_ne!(evaluator.score(&expr, 0), default);
+    }
+
+    #[test]
+    fn bytecode_cases_jit_eq() {
+        let mut rng = thread_rng();
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/cases");
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let prog: Program = text
+                .parse()
+                .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+            let func = Jit::<Linux_x86_64>::jit_prog(&prog)
+                .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+
+            for _ in 0..TESTS {
+                let hash_state = rng.gen();
+                let byte: u8 = rng.gen();
+
+                assert_eq!(
+                    func.call(hash_state, byte as u64),
+                    prog.eval(hash_state, byte),
+                    "{} disagrees on state {} and byte {}",
+                    path.display(),
+                    hash_state,
+                    byte
+                );
+            }
+        }
+    }
 }
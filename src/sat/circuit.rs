@@ -0,0 +1,180 @@
+use super::solver::{Lit, Solver};
+use std::collections::HashMap;
+
+/// the bits of a 64 bit value, least significant first
+pub type Word = [Lit; 64];
+
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+enum Gate {
+    And,
+    Xor,
+}
+
+/// a boolean circuit encoded into CNF as it is built, gates with constant inputs are folded and
+/// gates that were already built are reused, so equal subcircuits end up as the same literals
+pub struct Circuit {
+    pub solver: Solver,
+    true_lit: Lit,
+    gates: HashMap<(Gate, Lit, Lit), Lit>,
+}
+
+impl Circuit {
+    pub fn new() -> Circuit {
+        let mut solver = Solver::new();
+        let true_lit = solver.new_var();
+        solver.add_clause(&[true_lit]);
+
+        Circuit {
+            solver,
+            true_lit,
+            gates: HashMap::new(),
+        }
+    }
+
+    pub fn constant(&self, bit: bool) -> Lit {
+        if bit {
+            self.true_lit
+        } else {
+            !self.true_lit
+        }
+    }
+
+    /// the value of a constant literal, if it is one
+    fn known(&self, lit: Lit) -> Option<bool> {
+        (lit.var() == self.true_lit.var()).then_some(lit == self.true_lit)
+    }
+
+    pub fn and(&mut self, a: Lit, b: Lit) -> Lit {
+        match (self.known(a), self.known(b)) {
+            (Some(false), _) | (_, Some(false)) => return self.constant(false),
+            (Some(true), _) => return b,
+            (_, Some(true)) => return a,
+            _ if a == b => return a,
+            _ if a == !b => return self.constant(false),
+            _ => (),
+        }
+
+        let key = (Gate::And, a.min(b), a.max(b));
+        if let Some(out) = self.gates.get(&key) {
+            return *out;
+        }
+
+        let out = self.solver.new_var();
+        self.solver.add_clause(&[!out, a]);
+        self.solver.add_clause(&[!out, b]);
+        self.solver.add_clause(&[out, !a, !b]);
+        self.gates.insert(key, out);
+        out
+    }
+
+    pub fn or(&mut self, a: Lit, b: Lit) -> Lit {
+        !self.and(!a, !b)
+    }
+
+    pub fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        match (self.known(a), self.known(b)) {
+            (Some(bit), _) => return if bit { !b } else { b },
+            (_, Some(bit)) => return if bit { !a } else { a },
+            _ if a == b => return self.constant(false),
+            _ if a == !b => return self.constant(true),
+            _ => (),
+        }
+
+        // negating an input negates the output, so gates are only built on positive literals
+        let negated = a.is_negated() != b.is_negated();
+        let (a, b) = (Lit::new(a.var(), false), Lit::new(b.var(), false));
+        let key = (Gate::Xor, a.min(b), a.max(b));
+
+        let out = match self.gates.get(&key) {
+            Some(out) => *out,
+            None => {
+                let out = self.solver.new_var();
+                self.solver.add_clause(&[!out, a, b]);
+                self.solver.add_clause(&[!out, !a, !b]);
+                self.solver.add_clause(&[out, !a, b]);
+                self.solver.add_clause(&[out, a, !b]);
+                self.gates.insert(key, out);
+                out
+            }
+        };
+        if negated {
+            !out
+        } else {
+            out
+        }
+    }
+
+    /// `b` if `select` is set, `a` otherwise
+    pub fn mux(&mut self, select: Lit, a: Lit, b: Lit) -> Lit {
+        let (a, b) = (self.and(!select, a), self.and(select, b));
+        self.or(a, b)
+    }
+
+    pub fn input(&mut self) -> Word {
+        [(); 64].map(|_| self.solver.new_var())
+    }
+
+    /// a hash state and a byte, as the low 8 bits of a word
+    pub fn inputs(&mut self) -> (Word, Word) {
+        let state = self.input();
+        let byte_bits = self.input();
+        let byte = std::array::from_fn(|bit| {
+            if bit < 8 {
+                byte_bits[bit]
+            } else {
+                self.constant(false)
+            }
+        });
+        (state, byte)
+    }
+
+    pub fn word(&self, num: u64) -> Word {
+        std::array::from_fn(|bit| self.constant(num >> bit & 1 == 1))
+    }
+
+    pub fn add_words(&mut self, a: &Word, b: &Word) -> Word {
+        let mut carry = self.constant(false);
+        std::array::from_fn(|bit| {
+            let half = self.xor(a[bit], b[bit]);
+            let sum = self.xor(half, carry);
+            let (both, carried) = (self.and(a[bit], b[bit]), self.and(half, carry));
+            carry = self.or(both, carried);
+            sum
+        })
+    }
+
+    pub fn xor_words(&mut self, a: &Word, b: &Word) -> Word {
+        std::array::from_fn(|bit| self.xor(a[bit], b[bit]))
+    }
+
+    /// rotate left by the low 6 bits of `amount` with a barrel shifter, which folds away to
+    /// wiring when the amount is constant
+    pub fn rotate_left(&mut self, a: &Word, amount: &Word) -> Word {
+        let mut word = *a;
+        for (stage, select) in amount[..6].iter().enumerate() {
+            let shift = 1 << stage;
+            word = std::array::from_fn(|bit| {
+                self.mux(*select, word[bit], word[(bit + 64 - shift) % 64])
+            });
+        }
+        word
+    }
+
+    pub fn rotate_right(&mut self, a: &Word, amount: &Word) -> Word {
+        let mut word = *a;
+        for (stage, select) in amount[..6].iter().enumerate() {
+            let shift = 1 << stage;
+            word =
+                std::array::from_fn(|bit| self.mux(*select, word[bit], word[(bit + shift) % 64]));
+        }
+        word
+    }
+
+    /// the value of a word in the solver's model
+    pub fn value(&self, word: &Word) -> u64 {
+        word.iter()
+            .enumerate()
+            .filter(|(_, lit)| self.solver.value(**lit) == Some(true))
+            .fold(0, |acc, (bit, _)| acc | 1 << bit)
+    }
+}
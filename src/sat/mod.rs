@@ -0,0 +1,200 @@
+pub mod circuit;
+pub mod solver;
+
+use crate::bytecode::code::{Instruction, Program, Value};
+use crate::expr::expr::{Expr, Tag};
+use circuit::{Circuit, Word};
+use std::fmt;
+
+/// encode the function of the hash state and a byte that a hasher computes for one byte as a
+/// circuit
+pub trait Blast {
+    fn blast(&self, circuit: &mut Circuit, state: &Word, byte: &Word) -> Word;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Equivalence {
+    /// the results are equal for every hash state and byte
+    Equivalent,
+    /// a hash state and byte the results differ on
+    Counterexample { state: u64, byte: u8 },
+}
+
+/// prove that two hashers compute the same function for a single byte, or find an input they
+/// differ on, by asking the solver for an input where some bit of their results differs
+pub fn equivalent<A: Blast, B: Blast>(a: &A, b: &B) -> Equivalence {
+    let mut circuit = Circuit::new();
+    let (state, byte) = circuit.inputs();
+
+    let (a, b) = (
+        a.blast(&mut circuit, &state, &byte),
+        b.blast(&mut circuit, &state, &byte),
+    );
+    let differs = circuit.xor_words(&a, &b);
+    circuit.solver.add_clause(&differs);
+
+    if circuit.solver.solve() {
+        Equivalence::Counterexample {
+            state: circuit.value(&state),
+            byte: circuit.value(&byte) as u8,
+        }
+    } else {
+        Equivalence::Equivalent
+    }
+}
+
+impl Blast for Expr<Tag> {
+    fn blast(&self, circuit: &mut Circuit, state: &Word, byte: &Word) -> Word {
+        let (a, b) = match self {
+            Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+                (a.blast(circuit, state, byte), b.blast(circuit, state, byte))
+            }
+            Expr::Tag(Tag::Const(num)) => return circuit.word(*num),
+            Expr::Tag(Tag::HashState) => return *state,
+            Expr::Tag(Tag::Byte) => return *byte,
+        };
+
+        match self {
+            Expr::Add(..) => circuit.add_words(&a, &b),
+            Expr::Xor(..) => circuit.xor_words(&a, &b),
+            Expr::RotLeft(..) => circuit.rotate_left(&a, &b),
+            Expr::RotRight(..) => circuit.rotate_right(&a, &b),
+            Expr::Tag(_) => unreachable!(),
+        }
+    }
+}
+
+impl Blast for Program {
+    /// follows `Program::eval`, so programs that wouldn't verify are still encoded
+    fn blast(&self, circuit: &mut Circuit, state: &Word, byte: &Word) -> Word {
+        let mut mem = vec![circuit.word(0); 2.max(self.biggest_ptr() + 1)];
+        mem[0] = *state;
+        mem[1] = *byte;
+
+        for instr in &self.instructions {
+            let (dst, src) = match instr {
+                Instruction::MoveAbs(dst, num) => {
+                    mem[*dst] = circuit.word(*num);
+                    continue;
+                }
+                Instruction::Move(dst, src)
+                | Instruction::Add(dst, src)
+                | Instruction::Xor(dst, src)
+                | Instruction::RotLeft(dst, src)
+                | Instruction::RotRight(dst, src) => (*dst, src),
+            };
+            let src = match src {
+                Value::Immediate(num) => circuit.word(u64::from(*num)),
+                Value::Reference(src) => mem[*src],
+            };
+
+            mem[dst] = match instr {
+                Instruction::Move(..) => src,
+                Instruction::Add(..) => circuit.add_words(&mem[dst], &src),
+                Instruction::Xor(..) => circuit.xor_words(&mem[dst], &src),
+                Instruction::RotLeft(..) => circuit.rotate_left(&mem[dst], &src),
+                Instruction::RotRight(..) => circuit.rotate_right(&mem[dst], &src),
+                Instruction::MoveAbs(..) => unreachable!(),
+            };
+        }
+
+        match self.result {
+            Value::Immediate(num) => circuit.word(u64::from(num)),
+            Value::Reference(idx) => mem[idx],
+        }
+    }
+}
+
+impl fmt::Display for Equivalence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Equivalence::Equivalent => write!(f, "equivalent for every state and byte"),
+            Equivalence::Counterexample { state, byte } => {
+                write!(f, "different on state {:#x} and byte {}", state, byte)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bytecode::gen;
+    use crate::expr::parse::parse;
+    use crate::hash::Hash;
+    use crate::ssa::code::Function;
+    use rand::prelude::*;
+
+    #[test]
+    fn proves_rewrites() {
+        let cases = [
+            ("(state + byte)", "(byte + state)"),
+            ("((state + 5) + (byte + 7))", "((state + byte) + 12)"),
+            ("((state << 13) << 60)", "(state >> 55)"),
+            ("(state << (byte + 64))", "(state << byte)"),
+            ("((state xor byte) xor state)", "byte"),
+        ];
+
+        for (a, b) in cases {
+            let (a, b) = (parse(a).unwrap(), parse(b).unwrap());
+            assert_eq!(
+                equivalent(&a, &b),
+                Equivalence::Equivalent,
+                "{} and {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn counterexamples_differ() {
+        let mut rng = thread_rng();
+
+        for _ in 0..50 {
+            let (a, b) = (Expr::rand(&mut rng), Expr::rand(&mut rng));
+            if let Equivalence::Counterexample { state, byte } = equivalent(&a, &b) {
+                assert_ne!(
+                    a.hash_bytes(state, &[byte]),
+                    b.hash_bytes(state, &[byte]),
+                    "{} and {}",
+                    a,
+                    b
+                );
+            }
+        }
+
+        let (a, b) = (
+            parse("(state + byte)").unwrap(),
+            parse("(state xor byte)").unwrap(),
+        );
+        assert!(matches!(
+            equivalent(&a, &b),
+            Equivalence::Counterexample { .. }
+        ));
+    }
+
+    #[test]
+    fn programs_match_expressions() {
+        let mut rng = thread_rng();
+
+        for _ in 0..50 {
+            let expr = Expr::rand(&mut rng);
+            let emitted = gen::emit(&expr, 6);
+            let optimized = crate::ssa::gen::emit(&Function::from(&expr).optimize());
+
+            assert_eq!(
+                equivalent(&expr, &emitted),
+                Equivalence::Equivalent,
+                "{}",
+                expr
+            );
+            assert_eq!(
+                equivalent(&expr, &optimized),
+                Equivalence::Equivalent,
+                "{}",
+                expr
+            );
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::BinaryHeap;
+use std::ops::Not;
+
+/// a variable or its negation, the variable is `lit >> 1` and the low bit is set if negated
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Lit(u32);
+
+pub type Var = usize;
+
+/// the number of conflicts in the first run between restarts, later runs follow the luby sequence
+const RESTART_BASE: usize = 100;
+const ACTIVITY_DECAY: f64 = 0.95;
+
+impl Lit {
+    pub fn new(var: Var, negated: bool) -> Lit {
+        Lit((var as u32) << 1 | negated as u32)
+    }
+
+    pub fn var(self) -> Var {
+        (self.0 >> 1) as Var
+    }
+
+    pub fn is_negated(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    fn idx(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+/// a conflict driven clause learning solver, with two watched literals, first UIP learning,
+/// activity based branching with phase saving and luby restarts
+#[derive(Default)]
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    /// the clauses watching each literal, visited when the literal becomes false
+    watches: Vec<Vec<usize>>,
+    assigns: Vec<Option<bool>>,
+    /// the clause that implied each assigned variable, `None` for decisions and level 0 units
+    reasons: Vec<Option<usize>>,
+    levels: Vec<usize>,
+    trail: Vec<Lit>,
+    /// the trail length at the start of each decision level
+    trail_lim: Vec<usize>,
+    /// the next trail entry to propagate
+    queue_head: usize,
+    activity: Vec<f64>,
+    activity_inc: f64,
+    /// unassigned variables by activity, entries are stale once a variable is bumped or assigned
+    order: BinaryHeap<(u64, Var)>,
+    /// the last value of each variable, tried first when it is decided
+    phases: Vec<bool>,
+    seen: Vec<bool>,
+    /// a clause was empty or two units conflicted, so no assignment can satisfy the clauses
+    unsat: bool,
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver {
+            activity_inc: 1.,
+            ..Solver::default()
+        }
+    }
+
+    pub fn new_var(&mut self) -> Lit {
+        let var = self.assigns.len();
+        self.watches.extend([Vec::new(), Vec::new()]);
+        self.assigns.push(None);
+        self.reasons.push(None);
+        self.levels.push(0);
+        self.activity.push(0.);
+        self.phases.push(false);
+        self.seen.push(false);
+        self.order.push((0f64.to_bits(), var));
+        Lit::new(var, false)
+    }
+
+    pub fn value(&self, lit: Lit) -> Option<bool> {
+        self.assigns[lit.var()].map(|value| value != lit.is_negated())
+    }
+
+    /// add a clause, after a `solve` this drops its assignment, but keeps the learnt clauses
+    /// since they follow from the others
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        self.backtrack(0);
+
+        let mut lits = lits.to_vec();
+        lits.sort_unstable();
+        lits.dedup();
+
+        let tautology = lits.windows(2).any(|pair| pair[0] == !pair[1]);
+        if tautology || lits.iter().any(|lit| self.value(*lit) == Some(true)) {
+            return;
+        }
+        lits.retain(|lit| self.value(*lit).is_none());
+
+        match lits[..] {
+            [] => self.unsat = true,
+            [unit] => {
+                self.assign(unit, None);
+                self.unsat |= self.propagate().is_some();
+            }
+            _ => {
+                self.attach(lits);
+            }
+        }
+    }
+
+    /// find an assignment satisfying every clause, which `value` then reads, or return false if
+    /// there is none
+    pub fn solve(&mut self) -> bool {
+        if self.unsat {
+            return false;
+        }
+
+        let mut restarts = 0;
+        let mut conflicts = 0;
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.trail_lim.is_empty() {
+                    self.unsat = true;
+                    return false;
+                }
+
+                let (learnt, level) = self.analyze(conflict);
+                self.backtrack(level);
+                match learnt[..] {
+                    [unit] => self.assign(unit, None),
+                    _ => {
+                        let asserting = learnt[0];
+                        let clause = self.attach(learnt);
+                        self.assign(asserting, Some(clause));
+                    }
+                }
+
+                self.activity_inc /= ACTIVITY_DECAY;
+                conflicts += 1;
+                if conflicts >= RESTART_BASE * luby(restarts) {
+                    self.backtrack(0);
+                    restarts += 1;
+                    conflicts = 0;
+                }
+            } else {
+                let Some(var) = self.pick_branch() else {
+                    return true;
+                };
+                self.trail_lim.push(self.trail.len());
+                self.assign(Lit::new(var, !self.phases[var]), None);
+            }
+        }
+    }
+
+    /// add a clause of at least two unassigned literals, watching the first two
+    fn attach(&mut self, lits: Vec<Lit>) -> usize {
+        let clause = self.clauses.len();
+        self.watches[lits[0].idx()].push(clause);
+        self.watches[lits[1].idx()].push(clause);
+        self.clauses.push(lits);
+        clause
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.assigns[var] = Some(!lit.is_negated());
+        self.reasons[var] = reason;
+        self.levels[var] = self.trail_lim.len();
+        self.trail.push(lit);
+    }
+
+    /// assign the literals implied by the trail, returning a clause with every literal false if
+    /// there is one
+    fn propagate(&mut self) -> Option<usize> {
+        while self.queue_head < self.trail.len() {
+            let false_lit = !self.trail[self.queue_head];
+            self.queue_head += 1;
+
+            let mut watchers = std::mem::take(&mut self.watches[false_lit.idx()]);
+            let mut kept = 0;
+            let mut conflict = None;
+
+            for idx in 0..watchers.len() {
+                let clause_idx = watchers[idx];
+                if conflict.is_some() {
+                    watchers[kept] = clause_idx;
+                    kept += 1;
+                    continue;
+                }
+
+                let clause = &mut self.clauses[clause_idx];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+
+                let assigns = &self.assigns;
+                let value = |lit: Lit| assigns[lit.var()].map(|value| value != lit.is_negated());
+                if value(clause[0]) == Some(true) {
+                    watchers[kept] = clause_idx;
+                    kept += 1;
+                    continue;
+                }
+
+                // move the watch to any literal that isn't false
+                if let Some(other) = (2..clause.len()).find(|k| value(clause[*k]) != Some(false)) {
+                    clause.swap(1, other);
+                    self.watches[clause[1].idx()].push(clause_idx);
+                    continue;
+                }
+
+                watchers[kept] = clause_idx;
+                kept += 1;
+                match value(clause[0]) {
+                    Some(false) => conflict = Some(clause_idx),
+                    _ => {
+                        let implied = clause[0];
+                        self.assign(implied, Some(clause_idx));
+                    }
+                }
+            }
+
+            watchers.truncate(kept);
+            watchers.append(&mut self.watches[false_lit.idx()]);
+            self.watches[false_lit.idx()] = watchers;
+
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    /// learn the first UIP clause of a conflict, with its asserting literal first and a literal
+    /// of the level to backtrack to second
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let level = self.trail_lim.len();
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut clause = conflict;
+        let mut trail_idx = self.trail.len();
+        let mut uip = None;
+
+        loop {
+            // the first literal of a reason clause is the one it implied
+            let skip = usize::from(uip.is_some());
+            for k in skip..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                let var = lit.var();
+                if self.seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+
+                self.seen[var] = true;
+                self.bump(var);
+                if self.levels[var] == level {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+
+            let lit = loop {
+                trail_idx -= 1;
+                if self.seen[self.trail[trail_idx].var()] {
+                    break self.trail[trail_idx];
+                }
+            };
+            self.seen[lit.var()] = false;
+            uip = Some(lit);
+            pending -= 1;
+            if pending == 0 {
+                break;
+            }
+            clause = self.reasons[lit.var()].expect("only the UIP can be a decision");
+        }
+
+        learnt[0] = !uip.unwrap();
+        for lit in &learnt[1..] {
+            self.seen[lit.var()] = false;
+        }
+
+        let mut backtrack_level = 0;
+        if let Some(deepest) = (1..learnt.len()).max_by_key(|k| self.levels[learnt[*k].var()]) {
+            learnt.swap(1, deepest);
+            backtrack_level = self.levels[learnt[1].var()];
+        }
+        (learnt, backtrack_level)
+    }
+
+    fn bump(&mut self, var: Var) {
+        self.activity[var] += self.activity_inc;
+        if self.activity[var] > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.activity_inc *= 1e-100;
+            self.order = (0..self.assigns.len())
+                .filter(|var| self.assigns[*var].is_none())
+                .map(|var| (self.activity[var].to_bits(), var))
+                .collect();
+        }
+        self.order.push((self.activity[var].to_bits(), var));
+    }
+
+    fn pick_branch(&mut self) -> Option<Var> {
+        // positive floats order the same as their bits
+        while let Some((activity, var)) = self.order.pop() {
+            if self.assigns[var].is_none() && activity == self.activity[var].to_bits() {
+                return Some(var);
+            }
+        }
+        None
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.trail_lim.len() <= level {
+            return;
+        }
+
+        for lit in self.trail.drain(self.trail_lim[level]..) {
+            let var = lit.var();
+            self.assigns[var] = None;
+            self.reasons[var] = None;
+            self.phases[var] = !lit.is_negated();
+            self.order.push((self.activity[var].to_bits(), var));
+        }
+        self.queue_head = self.trail.len();
+        self.trail_lim.truncate(level);
+    }
+}
+
+/// the luby sequence 1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8, ...
+fn luby(mut idx: usize) -> usize {
+    let (mut size, mut len) = (1, 0);
+    while size < idx + 1 {
+        len += 1;
+        size = 2 * size + 1;
+    }
+    while size - 1 != idx {
+        size = (size - 1) / 2;
+        len -= 1;
+        idx %= size;
+    }
+    1 << len
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn luby_sequence() {
+        let seq: Vec<_> = (0..15).map(luby).collect();
+        assert_eq!(seq, [1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+
+    /// every pigeon is in a hole and no two share one, which can't be satisfied with fewer holes
+    /// than pigeons
+    fn pigeonhole(pigeons: usize, holes: usize) -> bool {
+        let mut solver = Solver::new();
+        let vars: Vec<Vec<Lit>> = (0..pigeons)
+            .map(|_| (0..holes).map(|_| solver.new_var()).collect())
+            .collect();
+
+        for pigeon in &vars {
+            solver.add_clause(pigeon);
+        }
+        for (a, pigeon) in vars.iter().enumerate() {
+            for other in &vars[a + 1..] {
+                for (hole, other_hole) in pigeon.iter().zip(other) {
+                    solver.add_clause(&[!*hole, !*other_hole]);
+                }
+            }
+        }
+        solver.solve()
+    }
+
+    #[test]
+    fn pigeonholes() {
+        assert!(pigeonhole(5, 5));
+        assert!(!pigeonhole(6, 5));
+    }
+
+    #[test]
+    fn models_satisfy_random_3sat() {
+        let mut rng = thread_rng();
+
+        for _ in 0..200 {
+            let vars = rng.gen_range(5..40);
+            // around the threshold of 4.26 clauses per variable, so both outcomes are common
+            let clauses: Vec<Vec<(usize, bool)>> = (0..vars * 426 / 100)
+                .map(|_| {
+                    (0..3)
+                        .map(|_| (rng.gen_range(0..vars), rng.gen()))
+                        .collect()
+                })
+                .collect();
+
+            let mut solver = Solver::new();
+            let lits: Vec<_> = (0..vars).map(|_| solver.new_var()).collect();
+            for clause in &clauses {
+                let clause: Vec<_> = clause
+                    .iter()
+                    .map(|(var, neg)| Lit::new(lits[*var].var(), *neg))
+                    .collect();
+                solver.add_clause(&clause);
+            }
+
+            if solver.solve() {
+                for clause in &clauses {
+                    assert!(clause
+                        .iter()
+                        .any(|(var, neg)| solver.value(Lit::new(lits[*var].var(), *neg))
+                            == Some(true)));
+                }
+            } else if vars <= 16 {
+                // no assignment satisfies every clause
+                let satisfiable = (0..1u32 << vars).any(|bits| {
+                    clauses.iter().all(|clause| {
+                        clause
+                            .iter()
+                            .any(|(var, neg)| (bits >> var & 1 == 1) != *neg)
+                    })
+                });
+                assert!(!satisfiable);
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use crate::expr::expr::{Expr, Operator, Tag};
+use crate::hash::Hash;
+use crate::sat::circuit::{Circuit, Word};
+use crate::sat::solver::Lit;
+use crate::sat::{self, Blast, Equivalence};
+use rand::Rng;
+use std::fmt;
+
+/// the number of random inputs tried for each input bit before the solver is asked whether an
+/// output bit depends on it
+const DEPENDENCY_SAMPLES: usize = 64;
+/// the state bits followed by the byte bits
+const INPUT_BITS: usize = 64 + 8;
+
+/// a requirement on the hash a shape is tagged into
+#[derive(Clone)]
+pub enum Spec {
+    /// hashing `bytes` from `init` gives `hash`
+    Vector {
+        init: u64,
+        bytes: Vec<u8>,
+        hash: u64,
+    },
+    /// every bit of `outputs` depends on every bit of the state and the byte, that is for each of
+    /// them there is a state and byte where flipping the input bit flips the output bit
+    Depends { outputs: u64 },
+    /// the result is the same as the expression's for every state and byte
+    Matches(Expr<Tag>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// no tagging of the shape meets the specification
+    Unsatisfiable,
+    /// the solver proposed as many candidates as it was allowed to
+    OutOfIterations,
+}
+
+/// a reason a candidate doesn't meet the specification
+enum Counterexample {
+    /// the candidate should hash a byte from a state to `hash`
+    Output { state: u64, byte: u8, hash: u64 },
+    /// flipping the input bit never flips the output bit
+    Independent { input: usize, output: usize },
+}
+
+/// the tag of a leaf as solver variables, the state or the byte if their variable is set and
+/// the constant otherwise
+struct Leaf {
+    is_state: Lit,
+    is_byte: Lit,
+    constant: Word,
+}
+
+/// counterexample guided synthesis of the tags of a shape
+///
+/// the solver proposes tags that meet the specification on the inputs it knows about, the
+/// candidate is then checked on every input, and the inputs it fails on are added to the
+/// solver's constraints, until a candidate passes or the solver proves that none can
+pub struct Cegis<'s> {
+    shape: &'s Expr<()>,
+    specs: &'s [Spec],
+    circuit: Circuit,
+    leaves: Vec<Leaf>,
+    /// the number of candidates the solver proposed
+    pub iterations: usize,
+}
+
+impl<'s> Cegis<'s> {
+    pub fn new(shape: &'s Expr<()>, specs: &'s [Spec]) -> Cegis<'s> {
+        let mut circuit = Circuit::new();
+        let leaves = (0..shape.len())
+            .map(|_| {
+                let (is_state, is_byte) = (circuit.solver.new_var(), circuit.solver.new_var());
+                circuit.solver.add_clause(&[!is_state, !is_byte]);
+                Leaf {
+                    is_state,
+                    is_byte,
+                    constant: circuit.input(),
+                }
+            })
+            .collect();
+
+        let mut cegis = Cegis {
+            shape,
+            specs,
+            circuit,
+            leaves,
+            iterations: 0,
+        };
+        // test vectors are encoded exactly, so candidates always meet them
+        for spec in specs {
+            if let Spec::Vector { init, bytes, hash } = spec {
+                cegis.require_vector(*init, bytes, *hash);
+            }
+        }
+        cegis
+    }
+
+    /// find tags that meet every spec, proposing at most `max_iterations` candidates
+    pub fn synthesize<R: Rng>(
+        &mut self,
+        max_iterations: usize,
+        rng: &mut R,
+    ) -> Result<Expr<Tag>, Failure> {
+        while self.iterations < max_iterations {
+            self.iterations += 1;
+            if !self.circuit.solver.solve() {
+                return Err(Failure::Unsatisfiable);
+            }
+
+            let candidate = self.candidate();
+            match self.verify(&candidate, rng) {
+                None => return Ok(candidate),
+                Some(Counterexample::Output { state, byte, hash }) => {
+                    self.require_vector(state, &[byte], hash)
+                }
+                Some(Counterexample::Independent { input, output }) => {
+                    self.require_dependency(input, output)
+                }
+            }
+        }
+        Err(Failure::OutOfIterations)
+    }
+
+    /// the tags of the solver's current model
+    fn candidate(&self) -> Expr<Tag> {
+        let mut tags = self.leaves.iter().map(|leaf| {
+            let value = |lit| self.circuit.solver.value(lit) == Some(true);
+            if value(leaf.is_state) {
+                Tag::HashState
+            } else if value(leaf.is_byte) {
+                Tag::Byte
+            } else {
+                Tag::Const(self.circuit.value(&leaf.constant))
+            }
+        });
+        tag(self.shape, &mut tags)
+    }
+
+    /// the first spec the candidate doesn't meet, every counterexample makes the solver's
+    /// problem larger, so only one is added at a time
+    fn verify<R: Rng>(&self, candidate: &Expr<Tag>, rng: &mut R) -> Option<Counterexample> {
+        self.specs.iter().find_map(|spec| match spec {
+            Spec::Vector { .. } => None,
+            Spec::Depends { outputs } => independent(candidate, *outputs, rng)
+                .map(|(input, output)| Counterexample::Independent { input, output }),
+            Spec::Matches(expr) => match sat::equivalent(candidate, expr) {
+                Equivalence::Equivalent => None,
+                Equivalence::Counterexample { state, byte } => Some(Counterexample::Output {
+                    state,
+                    byte,
+                    hash: expr.hash_bytes(state, &[byte]),
+                }),
+            },
+        })
+    }
+
+    /// the symbolic result of the shape for a state and byte
+    fn apply(&mut self, state: &Word, byte: &Word) -> Word {
+        let mut leaves = self.leaves.iter();
+        apply(self.shape, &mut self.circuit, &mut leaves, state, byte)
+    }
+
+    fn require_vector(&mut self, init: u64, bytes: &[u8], hash: u64) {
+        let mut state = self.circuit.word(init);
+        for byte in bytes {
+            let byte = self.circuit.word(u64::from(*byte));
+            state = self.apply(&state, &byte);
+        }
+
+        for (bit, lit) in state.iter().enumerate() {
+            let lit = if hash >> bit & 1 == 1 { *lit } else { !*lit };
+            self.circuit.solver.add_clause(&[lit]);
+        }
+    }
+
+    /// require a state and byte, left to the solver, where flipping the input bit flips the
+    /// output bit
+    fn require_dependency(&mut self, input: usize, output: usize) {
+        let (state, byte) = self.circuit.inputs();
+        let (flipped_state, flipped_byte) = flip(&state, &byte, input);
+
+        let (a, b) = (
+            self.apply(&state, &byte),
+            self.apply(&flipped_state, &flipped_byte),
+        );
+        let differs = self.circuit.xor(a[output], b[output]);
+        self.circuit.solver.add_clause(&[differs]);
+    }
+}
+
+/// the inputs with one of their bits flipped, a state bit below 64 and a byte bit after
+fn flip(state: &Word, byte: &Word, input: usize) -> (Word, Word) {
+    let (mut state, mut byte) = (*state, *byte);
+    if input < 64 {
+        state[input] = !state[input];
+    } else {
+        byte[input - 64] = !byte[input - 64];
+    }
+    (state, byte)
+}
+
+fn apply<'l>(
+    shape: &Expr<()>,
+    circuit: &mut Circuit,
+    leaves: &mut impl Iterator<Item = &'l Leaf>,
+    state: &Word,
+    byte: &Word,
+) -> Word {
+    let (a, b) = match shape {
+        Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => (
+            apply(a, circuit, leaves, state, byte),
+            apply(b, circuit, leaves, state, byte),
+        ),
+        Expr::Tag(()) => {
+            let leaf = leaves.next().expect("a leaf for every tag");
+            return std::array::from_fn(|bit| {
+                let other = circuit.mux(leaf.is_byte, leaf.constant[bit], byte[bit]);
+                circuit.mux(leaf.is_state, other, state[bit])
+            });
+        }
+    };
+
+    match shape {
+        Expr::Add(..) => circuit.add_words(&a, &b),
+        Expr::Xor(..) => circuit.xor_words(&a, &b),
+        Expr::RotLeft(..) => circuit.rotate_left(&a, &b),
+        Expr::RotRight(..) => circuit.rotate_right(&a, &b),
+        Expr::Tag(()) => unreachable!(),
+    }
+}
+
+fn tag(shape: &Expr<()>, tags: &mut impl Iterator<Item = Tag>) -> Expr<Tag> {
+    let (a, b, op) = match shape {
+        Expr::Add(a, b) => (a, b, Expr::Add as Operator),
+        Expr::Xor(a, b) => (a, b, Expr::Xor as Operator),
+        Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator),
+        Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator),
+        Expr::Tag(()) => return Expr::Tag(tags.next().expect("a tag for every leaf")),
+    };
+    let a = tag(a, tags);
+    op(Box::new(a), Box::new(tag(b, tags)))
+}
+
+/// an input bit and a bit of `outputs` that never flips with it, found by flipping input bits of
+/// random inputs and asking the solver about the pairs that never flipped
+fn independent<R: Rng>(expr: &Expr<Tag>, outputs: u64, rng: &mut R) -> Option<(usize, usize)> {
+    for input in 0..INPUT_BITS {
+        let flip = |state: u64, byte: u8| {
+            if input < 64 {
+                (state ^ 1 << input, byte)
+            } else {
+                (state, byte ^ 1 << (input - 64))
+            }
+        };
+
+        let mut flipped = 0;
+        for _ in 0..DEPENDENCY_SAMPLES {
+            let (state, byte) = (rng.gen(), rng.gen());
+            let (other_state, other_byte) = flip(state, byte);
+            flipped |=
+                expr.hash_bytes(state, &[byte]) ^ expr.hash_bytes(other_state, &[other_byte]);
+        }
+
+        for output in (0..64).filter(|bit| (outputs & !flipped) >> bit & 1 == 1) {
+            if !can_flip(expr, input, output) {
+                return Some((input, output));
+            }
+        }
+    }
+    None
+}
+
+/// whether some state and byte flip the output bit when the input bit is flipped
+fn can_flip(expr: &Expr<Tag>, input: usize, output: usize) -> bool {
+    let mut circuit = Circuit::new();
+    let (state, byte) = circuit.inputs();
+    let (flipped_state, flipped_byte) = flip(&state, &byte, input);
+
+    let a = expr.blast(&mut circuit, &state, &byte);
+    let b = expr.blast(&mut circuit, &flipped_state, &flipped_byte);
+    let differs = circuit.xor(a[output], b[output]);
+    circuit.solver.add_clause(&[differs]);
+    circuit.solver.solve()
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Spec::Vector { init, bytes, hash } => {
+                write!(f, "{:02x?} hashes from {:#x} to {:#x}", bytes, init, hash)
+            }
+            Spec::Depends { outputs } => {
+                write!(
+                    f,
+                    "the output bits {:#x} depend on every input bit",
+                    outputs
+                )
+            }
+            Spec::Matches(expr) => write!(f, "computes the same function as {}", expr),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Unsatisfiable => write!(f, "no tagging of the shape meets the specification"),
+            Failure::OutOfIterations => write!(f, "ran out of iterations"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::{parse, parse_shape};
+    use rand::prelude::*;
+
+    #[test]
+    fn synthesizes_to_match() {
+        let mut rng = thread_rng();
+        let target = parse("(((state xor byte) + 12345) << 17)").unwrap();
+        let shape = target.shape();
+        let specs = [Spec::Matches(target.clone())];
+
+        let found = Cegis::new(&shape, &specs).synthesize(50, &mut rng).unwrap();
+        assert_eq!(sat::equivalent(&found, &target), Equivalence::Equivalent);
+    }
+
+    #[test]
+    fn meets_test_vectors() {
+        let mut rng = thread_rng();
+        let target = parse("((state xor (byte << 9)) + 987654321)").unwrap();
+        let shape = parse_shape("((_ xor (_ << _)) + _)").unwrap();
+
+        let specs: Vec<_> = (0..4)
+            .map(|_| {
+                let (init, bytes) = (rng.gen(), vec![rng.gen()]);
+                let hash = target.hash_bytes(init, &bytes);
+                Spec::Vector { init, bytes, hash }
+            })
+            .collect();
+
+        let found = Cegis::new(&shape, &specs).synthesize(10, &mut rng).unwrap();
+        for spec in &specs {
+            if let Spec::Vector { init, bytes, hash } = spec {
+                assert_eq!(found.hash_bytes(*init, bytes), *hash, "{}", found);
+            }
+        }
+    }
+
+    #[test]
+    fn makes_outputs_depend_on_inputs() {
+        let mut rng = thread_rng();
+        let shape = parse_shape("((_ xor _) << (_ + _))").unwrap();
+        let specs = [Spec::Depends { outputs: 0xff }];
+
+        let found = Cegis::new(&shape, &specs)
+            .synthesize(100, &mut rng)
+            .unwrap();
+        assert!(independent(&found, 0xff, &mut rng).is_none(), "{}", found);
+
+        // nothing that adds two leaves can make the lowest bit depend on higher bits
+        let shape = parse_shape("(_ + _)").unwrap();
+        let specs = [Spec::Depends { outputs: 1 }];
+        let result = Cegis::new(&shape, &specs).synthesize(100, &mut rng);
+        assert!(matches!(result, Err(Failure::Unsatisfiable)));
+    }
+}
@@ -0,0 +1,217 @@
+use super::pareto::Objectives;
+use crate::bytecode::code::Program;
+use crate::bytecode::verify::Target;
+use crate::expr::expr::{Expr, Tag};
+use crate::hash::avalanche::Avalanche;
+use crate::hash::distribution::Distribution;
+use crate::hash::throughput::Throughput;
+use crate::hash::{score_hasher, Metric};
+use crate::jit::code_vec::CodeVec;
+use crate::jit::linux::Linux_x86_64;
+use crate::jit_prog::Jit;
+use crate::seed;
+use crate::ssa::code::Function;
+use crate::ssa::gen::emit;
+use std::fmt;
+use std::mem::transmute;
+use std::str::FromStr;
+
+/// the number of bytes hashed in each throughput sample
+const THROUGHPUT_BYTES: usize = 1024;
+/// the number of throughput samples taken for every candidate
+const THROUGHPUT_SAMPLES: usize = 16;
+/// the length of the keys the avalanche matrix of a pareto candidate is measured on
+const AVALANCHE_KEY_LEN: usize = 8;
+/// the number of keys the avalanche matrix of a pareto candidate is measured on
+const AVALANCHE_SAMPLES: usize = 32;
+
+/// what the search ranks candidates on, higher scores are better
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Objective {
+    /// the avalanche score and any extra metrics
+    Quality,
+    /// the negated cycles per byte
+    Speed,
+    /// the quality minus `weight` times the cycles per byte
+    Combined { weight: f64 },
+}
+
+/// scores tagged expressions by jit compiling them, reusing the same code buffer for every one
+pub struct Evaluator {
+    code: Option<CodeVec>,
+    /// extra score terms on top of the average avalanche
+    pub metrics: Vec<Metric>,
+    pub objective: Objective,
+}
+
+impl Evaluator {
+    pub fn new(metrics: Vec<Metric>, objective: Objective) -> Self {
+        Evaluator {
+            code: Some(CodeVec::default()),
+            metrics,
+            objective,
+        }
+    }
+
+    /// compile an expression and run `f` on the compiled function
+    pub fn with_jit<T>(
+        &mut self,
+        tagged: &Expr<Tag>,
+        f: impl FnOnce(fn(u64, u64) -> u64) -> T,
+    ) -> T {
+        self.compile(tagged, |jit, _, _| f(jit))
+    }
+
+    /// compile an expression and run `f` on the compiled function, its bytecode and the size of
+    /// its machine code in bytes
+    fn compile<T>(
+        &mut self,
+        tagged: &Expr<Tag>,
+        f: impl FnOnce(fn(u64, u64) -> u64, &Program, usize) -> T,
+    ) -> T {
+        let prog = emit(&Function::from(tagged).optimize());
+        if let Err(err) = prog.verify(Target::Jit) {
+            panic!("{}\n{}", err, prog);
+        }
+
+        let mut asm = Linux_x86_64::new(self.code.take().unwrap());
+        Jit::asm_prog(&mut asm, &prog);
+        let (buffer, len, cap) = asm.finalize_with_cap();
+        let jit: fn(u64, u64) -> u64 = unsafe { transmute(buffer) };
+
+        let res = f(jit, &prog, len);
+        self.code = Some(unsafe { CodeVec::from_raw_parts(buffer as *mut u8, 0, cap) });
+        res
+    }
+
+    /// measure an expression on every objective of a pareto front, with the distribution
+    /// measured over `keys`
+    pub fn objectives(&mut self, tagged: &Expr<Tag>, seed: u64, keys: &[Vec<u8>]) -> Objectives {
+        let mut rng = seed::rng(seed);
+
+        self.compile(tagged, |jit, prog, code_bytes| {
+            let avalanche =
+                Avalanche::measure(&jit, 0, AVALANCHE_KEY_LEN, AVALANCHE_SAMPLES, &mut rng);
+            let distribution = Distribution::measure(&jit, 0, keys.iter().map(Vec::as_slice));
+            let throughput =
+                Throughput::measure(&jit, 0, THROUGHPUT_BYTES, THROUGHPUT_SAMPLES, &mut rng);
+
+            Objectives {
+                avalanche: 1. - avalanche.mean_bias(),
+                distribution: distribution.score(),
+                instructions: prog.instructions.len(),
+                code_bytes,
+                cycles_per_byte: throughput.cycles_per_byte,
+            }
+        })
+    }
+
+    /// score an expression on the objective, the same seed always gives the same quality score,
+    /// but the speed depends on the machine and how busy it is
+    pub fn score(&mut self, tagged: &Expr<Tag>, seed: u64) -> f64 {
+        match self.objective {
+            Objective::Quality => self.quality(tagged, seed),
+            Objective::Speed => -self.throughput(tagged, seed).cycles_per_byte,
+            Objective::Combined { weight } => {
+                self.quality(tagged, seed) - weight * self.throughput(tagged, seed).cycles_per_byte
+            }
+        }
+    }
+
+    /// the avalanche score plus the extra metrics
+    pub fn quality(&mut self, tagged: &Expr<Tag>, seed: u64) -> f64 {
+        let mut rng = seed::rng(seed);
+        let metrics = std::mem::take(&mut self.metrics);
+
+        let score = self.with_jit(tagged, |jit| {
+            // a hash that ignores the byte being hashed maps every key to the same value, so skip
+            // scoring it properly
+            if ignores_byte(jit, seed) {
+                return 0.;
+            }
+
+            score_hasher(jit, tagged.len(), 0, 10, 3, 50, 3, &mut rng)
+                + metrics
+                    .iter()
+                    .map(|metric| metric.score(&jit, 0, &mut rng))
+                    .sum::<f64>()
+        });
+
+        self.metrics = metrics;
+        score
+    }
+
+    /// how fast the compiled expression hashes
+    pub fn throughput(&mut self, tagged: &Expr<Tag>, seed: u64) -> Throughput {
+        let mut rng = seed::rng(seed);
+
+        self.with_jit(tagged, |jit| {
+            Throughput::measure(&jit, 0, THROUGHPUT_BYTES, THROUGHPUT_SAMPLES, &mut rng)
+        })
+    }
+}
+
+/// a cheap probe for whether a hash state update gives the same result for any byte
+fn ignores_byte(jit: fn(u64, u64) -> u64, seed: u64) -> bool {
+    (0..4).map(|idx| seed::derive(seed, idx)).all(|state| {
+        let hash = jit(state, 0);
+        [1, 0x80, 0xff].iter().all(|byte| jit(state, *byte) == hash)
+    })
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    /// `quality`, `speed`, or `combined:<weight>`
+    fn from_str(text: &str) -> Result<Objective, String> {
+        match text.split_once(':') {
+            None if text == "quality" => Ok(Objective::Quality),
+            None if text == "speed" => Ok(Objective::Speed),
+            Some(("combined", weight)) => weight
+                .parse()
+                .map(|weight| Objective::Combined { weight })
+                .map_err(|_| format!("`{}` is not a weight", weight)),
+            _ => Err(format!(
+                "unknown objective `{}`, expected quality, speed or combined:<weight>",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Objective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Objective::Quality => write!(f, "quality"),
+            Objective::Speed => write!(f, "speed"),
+            Objective::Combined { weight } => write!(f, "combined:{}", weight),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search::bfs::Search;
+    use crate::search::tag::Tagger;
+
+    #[test]
+    fn same_seed_same_score() {
+        let mut evaluator = Evaluator::new(Vec::new(), Objective::Quality);
+        let tagger = Tagger::default();
+
+        for (i, expr) in Search::default().take(100).enumerate() {
+            let seed = seed::derive(0, i as u64);
+            let tagged = tagger.annotate(&expr, &mut seed::rng(seed));
+
+            assert_eq!(
+                tagged.to_string(),
+                tagger.annotate(&expr, &mut seed::rng(seed)).to_string()
+            );
+            assert_eq!(
+                evaluator.score(&tagged, seed),
+                evaluator.score(&tagged, seed)
+            );
+        }
+    }
+}
@@ -0,0 +1,417 @@
+use super::tag::Tagger;
+use crate::expr::expr::{Expr, Operator, Tag};
+use crate::seed;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// the number of times a child that is too big is bred again before a parent is copied instead
+const REBREEDS: usize = 8;
+
+const OPERATORS: [Operator; 4] = [Expr::Add, Expr::Xor, Expr::RotLeft, Expr::RotRight];
+
+/// a tagged expression of the population, and the seed it was scored with, so it can be rescored
+/// with `best-hash score`
+#[derive(Clone)]
+pub struct Individual {
+    pub expr: Expr<Tag>,
+    /// `None` if the scoring function rejected the expression
+    pub score: Option<f64>,
+    pub seed: u64,
+}
+
+/// a genetic programming search over tagged expressions
+///
+/// every generation keeps the `elites` best expressions and breeds the rest of the next one from
+/// parents picked by tournaments, by subtree crossover and point mutations. Children with more
+/// than `max_leaves` leaves are bred again, and tournaments between expressions whose scores are
+/// within `parsimony` of each other are won by the smaller one, which keeps the expressions from
+/// growing without getting better
+pub struct Genetic {
+    /// the number of expressions in a generation
+    pub size: usize,
+    /// the number of expressions that compete for each parent
+    pub tournament: usize,
+    /// the number of best expressions copied into the next generation unchanged
+    pub elites: usize,
+    /// the probability a child is bred by crossover instead of copied from one parent
+    pub crossover: f64,
+    /// the probability a child bred by crossover is also mutated, one that is copied always is
+    pub mutation: f64,
+    pub max_leaves: usize,
+    pub parsimony: f64,
+    /// the current generation, best first once it has been scored
+    population: Vec<Individual>,
+    seed: u64,
+    rng: StdRng,
+    tagger: Tagger,
+    pub stats: Stats,
+}
+
+#[derive(Default, Debug)]
+pub struct Stats {
+    pub generations: usize,
+    /// the number of expressions scored in total
+    pub scored: u64,
+    /// the number of expressions the scoring function rejected
+    pub rejected: u64,
+    /// the number of children that stayed too big and were replaced by a copy of a parent
+    pub oversized: u64,
+}
+
+impl Genetic {
+    /// a search of `size` expressions a generation with at most `max_leaves` leaves each, whose
+    /// expressions are scored with seeds derived from `seed`, panics if `max_leaves` is less than
+    /// 2 since every expression has an operator
+    pub fn new(seed: u64, size: usize, max_leaves: usize) -> Genetic {
+        assert!(
+            max_leaves >= 2,
+            "expressions need at least 2 leaves, not {}",
+            max_leaves
+        );
+
+        Genetic {
+            size,
+            tournament: 4,
+            elites: 2,
+            crossover: 0.7,
+            mutation: 0.3,
+            max_leaves,
+            parsimony: 0.,
+            population: Vec::with_capacity(size),
+            seed,
+            rng: seed::rng(seed),
+            tagger: Tagger::default(),
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn population(&self) -> &[Individual] {
+        &self.population
+    }
+
+    /// the best expression scored so far, which elitism keeps in the population
+    pub fn best(&self) -> Option<&Individual> {
+        self.population.first().filter(|best| best.score.is_some())
+    }
+
+    /// score a random first generation, or breed and score the next one
+    ///
+    /// `score` is called with an expression and the seed to score it with, and returns `None`
+    /// for one that should never be picked as a parent, like a degenerate one
+    pub fn step(&mut self, mut score: impl FnMut(&Expr<Tag>, u64) -> Option<f64>) {
+        let children: Vec<Expr<Tag>> = if self.population.is_empty() {
+            (0..self.size).map(|_| self.random()).collect()
+        } else {
+            self.stats.generations += 1;
+            (self.elites.min(self.size)..self.size)
+                .map(|_| self.breed())
+                .collect()
+        };
+
+        self.population.truncate(self.elites);
+        for expr in children {
+            let seed = seed::derive(self.seed, self.stats.scored);
+            self.stats.scored += 1;
+            let score = score(&expr, seed);
+            if score.is_none() {
+                self.stats.rejected += 1;
+            }
+            self.population.push(Individual { expr, score, seed });
+        }
+        self.population.sort_by(|a, b| compare(b, a));
+    }
+
+    /// a random shape with between 2 and `max_leaves` leaves, tagged the way the bfs search tags
+    /// its shapes
+    fn random(&mut self) -> Expr<Tag> {
+        let leaves = self.rng.gen_range(2..=self.max_leaves);
+        let shape = random_shape(leaves, &mut self.rng);
+        self.tagger.annotate(&shape, &mut self.rng)
+    }
+
+    fn breed(&mut self) -> Expr<Tag> {
+        let parent = self.select().expr.clone();
+        if !self.rng.gen_bool(self.crossover) {
+            return mutate(&parent, &self.tagger, &mut self.rng);
+        }
+
+        let other = self.select().expr.clone();
+        for _ in 0..REBREEDS {
+            let mut child = crossover(&parent, &other, &mut self.rng);
+            if self.rng.gen_bool(self.mutation) {
+                child = mutate(&child, &self.tagger, &mut self.rng);
+            }
+            if child.len() <= self.max_leaves {
+                return child;
+            }
+        }
+        self.stats.oversized += 1;
+        parent
+    }
+
+    /// the winner of a tournament between random members of the population
+    fn select(&mut self) -> &Individual {
+        let mut winner = self.rng.gen_range(0..self.population.len());
+        for _ in 1..self.tournament {
+            let contender = self.rng.gen_range(0..self.population.len());
+            let (a, b) = (&self.population[contender], &self.population[winner]);
+            let tied = match (a.score, b.score) {
+                (Some(a), Some(b)) => (a - b).abs() <= self.parsimony,
+                (None, None) => true,
+                _ => false,
+            };
+            let better = if tied {
+                a.expr.len() < b.expr.len()
+            } else {
+                compare(a, b) == Ordering::Greater
+            };
+            if better {
+                winner = contender;
+            }
+        }
+        &self.population[winner]
+    }
+}
+
+/// order by score, with rejected expressions below every scored one
+fn compare(a: &Individual, b: &Individual) -> Ordering {
+    match (a.score, b.score) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+fn random_shape<R: Rng>(leaves: usize, rng: &mut R) -> Expr<()> {
+    if leaves == 1 {
+        return Expr::Tag(());
+    }
+    let left = rng.gen_range(1..leaves);
+    let (a, b) = (random_shape(left, rng), random_shape(leaves - left, rng));
+    match rng.gen_range(0..4) {
+        0 => Expr::Add(Box::new(a), Box::new(b)),
+        1 => Expr::Xor(Box::new(a), Box::new(b)),
+        2 => Expr::RotLeft(Box::new(a), Box::new(b)),
+        _ => Expr::RotRight(Box::new(a), Box::new(b)),
+    }
+}
+
+/// the number of nodes of an expression, leaves included
+fn nodes(expr: &Expr<Tag>) -> usize {
+    2 * expr.len() - 1
+}
+
+/// the subtree at an index of the nodes in preorder
+fn subtree(expr: &Expr<Tag>, idx: usize) -> &Expr<Tag> {
+    match expr {
+        _ if idx == 0 => expr,
+        Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+            if idx - 1 < nodes(a) {
+                subtree(a, idx - 1)
+            } else {
+                subtree(b, idx - 1 - nodes(a))
+            }
+        }
+        Expr::Tag(_) => unreachable!(),
+    }
+}
+
+/// where the node at an index of the nodes in preorder is, the way the tagger tells positions
+/// apart: the index of the operator it is an operand of, whether it is the right operand, and
+/// how deep it is
+fn position(expr: &Expr<Tag>, idx: usize) -> (Option<usize>, bool, usize) {
+    let (a, b, op) = match expr {
+        _ if idx == 0 => return (None, false, 0),
+        Expr::Add(a, b) => (a, b, 0),
+        Expr::Xor(a, b) => (a, b, 1),
+        Expr::RotLeft(a, b) => (a, b, 2),
+        Expr::RotRight(a, b) => (a, b, 3),
+        Expr::Tag(_) => unreachable!(),
+    };
+    let (child, idx, right) = if idx - 1 < nodes(a) {
+        (a, idx - 1, false)
+    } else {
+        (b, idx - 1 - nodes(a), true)
+    };
+    if idx == 0 {
+        (Some(op), right, 1)
+    } else {
+        let (parent, right, depth) = position(child, idx);
+        (parent, right, depth + 1)
+    }
+}
+
+/// the expression with the subtree at an index of the nodes in preorder replaced
+fn replace(
+    expr: &Expr<Tag>,
+    idx: usize,
+    with: &mut impl FnMut(&Expr<Tag>) -> Expr<Tag>,
+) -> Expr<Tag> {
+    if idx == 0 {
+        return with(expr);
+    }
+    let (a, b, op) = match expr {
+        Expr::Add(a, b) => (a, b, Expr::Add as Operator),
+        Expr::Xor(a, b) => (a, b, Expr::Xor as Operator),
+        Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator),
+        Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator),
+        Expr::Tag(_) => unreachable!(),
+    };
+    let (a, b) = if idx - 1 < nodes(a) {
+        (replace(a, idx - 1, with), (**b).clone())
+    } else {
+        ((**a).clone(), replace(b, idx - 1 - nodes(a), with))
+    };
+    op(Box::new(a), Box::new(b))
+}
+
+/// the indices of the nodes in preorder that satisfy a predicate
+fn indices(expr: &Expr<Tag>, pred: impl Fn(&Expr<Tag>) -> bool) -> Vec<usize> {
+    (0..nodes(expr))
+        .filter(|idx| pred(subtree(expr, *idx)))
+        .collect()
+}
+
+/// replace a random subtree of `a` with a random subtree of `b`
+pub fn crossover<R: Rng>(a: &Expr<Tag>, b: &Expr<Tag>, rng: &mut R) -> Expr<Tag> {
+    let donated = subtree(b, rng.gen_range(0..nodes(b))).clone();
+    replace(a, rng.gen_range(0..nodes(a)), &mut |_| donated.clone())
+}
+
+/// change a single node: swap an operator for another, retag a leaf with the tagger's odds for
+/// its position, flip a bit of a constant or change a constant rotation amount by one
+pub fn mutate<R: Rng>(expr: &Expr<Tag>, tagger: &Tagger, rng: &mut R) -> Expr<Tag> {
+    loop {
+        let mutation = rng.gen_range(0..4);
+        let candidates = indices(expr, |node| match (mutation, node) {
+            (0, Expr::Tag(_)) => false,
+            (0, _) | (1, Expr::Tag(_)) | (2, Expr::Tag(Tag::Const(_))) => true,
+            (3, Expr::RotLeft(_, b) | Expr::RotRight(_, b)) => {
+                matches!(**b, Expr::Tag(Tag::Const(_)))
+            }
+            _ => false,
+        });
+        if candidates.is_empty() {
+            continue;
+        }
+
+        let idx = candidates[rng.gen_range(0..candidates.len())];
+        let (parent, right, depth) = position(expr, idx);
+        return replace(expr, idx, &mut |node| match (mutation, node) {
+            (0, Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b)) => {
+                let op = match node {
+                    Expr::Add(..) => 0,
+                    Expr::Xor(..) => 1,
+                    Expr::RotLeft(..) => 2,
+                    _ => 3,
+                };
+                OPERATORS[(op + rng.gen_range(1..4)) % 4](a.clone(), b.clone())
+            }
+            (1, _) => Expr::Tag(tagger.tag_leaf(parent, right, depth, rng)),
+            (2, Expr::Tag(Tag::Const(num))) => {
+                Expr::Tag(Tag::Const(num ^ 1 << rng.gen_range(0..64)))
+            }
+            (3, Expr::RotLeft(a, b) | Expr::RotRight(a, b)) => {
+                let Expr::Tag(Tag::Const(num)) = **b else {
+                    unreachable!()
+                };
+                let num = if rng.gen() {
+                    num.wrapping_add(1)
+                } else {
+                    num.wrapping_sub(1)
+                };
+                let op = match node {
+                    Expr::RotLeft(..) => Expr::RotLeft as Operator,
+                    _ => Expr::RotRight as Operator,
+                };
+                op(a.clone(), Box::new(Expr::Tag(Tag::Const(num))))
+            }
+            _ => unreachable!(),
+        });
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} generations, {} expressions scored, {} rejected and {} children too big to keep",
+            self.generations, self.scored, self.rejected, self.oversized
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::closure::Hasher;
+    use crate::expr::parse::parse;
+    use crate::hash::score_hasher;
+    use rand::prelude::*;
+
+    #[test]
+    fn children_are_small_enough() {
+        let mut rng = thread_rng();
+
+        for _ in 0..200 {
+            let (a, b) = (random_shape(6, &mut rng), random_shape(6, &mut rng));
+            let tagger = Tagger::default();
+            let (a, b) = (tagger.annotate(&a, &mut rng), tagger.annotate(&b, &mut rng));
+
+            assert_eq!(mutate(&a, &tagger, &mut rng).len(), a.len(), "{}", a);
+            let child = crossover(&a, &b, &mut rng);
+            assert!(child.len() < a.len() + b.len(), "{} and {}", a, b);
+        }
+
+        let mut genetic = Genetic::new(rng.gen(), 20, 5);
+        for _ in 0..5 {
+            genetic.step(|expr, _| Some(expr.len() as f64));
+        }
+        assert!(genetic.population().iter().all(|ind| ind.expr.len() <= 5));
+        assert_eq!(genetic.population().len(), 20);
+    }
+
+    #[test]
+    fn positions_of_nodes() {
+        let expr = parse("((state xor byte) << (3 + byte))").unwrap();
+        let positions: Vec<_> = (0..nodes(&expr)).map(|idx| position(&expr, idx)).collect();
+        assert_eq!(
+            positions,
+            [
+                (None, false, 0),
+                (Some(2), false, 1),
+                (Some(1), false, 2),
+                (Some(1), true, 2),
+                (Some(2), true, 1),
+                (Some(0), false, 2),
+                (Some(0), true, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn best_never_gets_worse() {
+        let mut genetic = Genetic::new(thread_rng().gen(), 30, 8);
+        let mut best = f64::NEG_INFINITY;
+
+        for _ in 0..10 {
+            genetic.step(|expr, seed| {
+                let mut rng = seed::rng(seed);
+                Some(score_hasher(
+                    Hasher::from(expr),
+                    expr.len(),
+                    rng.gen(),
+                    4,
+                    4,
+                    8,
+                    1,
+                    &mut rng,
+                ))
+            });
+            let score = genetic.best().unwrap().score.unwrap();
+            assert!(score >= best);
+            best = score;
+        }
+    }
+}
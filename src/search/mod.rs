@@ -1,2 +1,10 @@
 pub mod bfs;
+pub mod cegis;
+pub mod eval;
+pub mod genetic;
+pub mod pareto;
+pub mod staged;
+pub mod superopt;
 pub mod tag;
+pub mod top;
+pub mod tune;
@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// everything a candidate is ranked on, without weighing them against each other
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Objectives {
+    /// one minus the mean bias of the avalanche matrix, higher is better
+    pub avalanche: f64,
+    /// the distribution score over a set of keys, higher is better
+    pub distribution: f64,
+    /// the number of bytecode instructions, lower is better
+    pub instructions: usize,
+    /// the size of the machine code, lower is better
+    pub code_bytes: usize,
+    /// lower is better
+    pub cycles_per_byte: f64,
+}
+
+impl Objectives {
+    /// whether `self` is at least as good as `other` on every objective, and better on one
+    pub fn dominates(&self, other: &Objectives) -> bool {
+        let orderings = [
+            self.avalanche.total_cmp(&other.avalanche),
+            self.distribution.total_cmp(&other.distribution),
+            other.instructions.cmp(&self.instructions),
+            other.code_bytes.cmp(&self.code_bytes),
+            other.cycles_per_byte.total_cmp(&self.cycles_per_byte),
+        ];
+
+        !orderings.contains(&Ordering::Less) && orderings.contains(&Ordering::Greater)
+    }
+}
+
+pub struct Candidate<T> {
+    pub objectives: Objectives,
+    /// the seed the candidate was generated and measured with
+    pub seed: u64,
+    pub item: T,
+}
+
+/// the candidates that no other candidate seen so far dominates
+pub struct Front<T> {
+    pub candidates: Vec<Candidate<T>>,
+}
+
+impl<T> Front<T> {
+    /// add a candidate unless it is dominated, dropping the candidates it dominates, and return
+    /// whether it was added
+    pub fn insert(&mut self, objectives: Objectives, seed: u64, item: T) -> bool {
+        if self
+            .candidates
+            .iter()
+            .any(|candidate| candidate.objectives.dominates(&objectives))
+        {
+            return false;
+        }
+
+        self.candidates
+            .retain(|candidate| !objectives.dominates(&candidate.objectives));
+        self.candidates.push(Candidate {
+            objectives,
+            seed,
+            item,
+        });
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+}
+
+impl<T> Default for Front<T> {
+    fn default() -> Self {
+        Front {
+            candidates: Vec::new(),
+        }
+    }
+}
+
+/// the front as tab separated values with a header, sorted by avalanche quality
+impl<T: fmt::Display> fmt::Display for Front<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut candidates: Vec<_> = self.candidates.iter().collect();
+        candidates.sort_by(|a, b| b.objectives.avalanche.total_cmp(&a.objectives.avalanche));
+
+        writeln!(
+            f,
+            "avalanche\tdistribution\tinstructions\tcode_bytes\tcycles_per_byte\tseed\texpr"
+        )?;
+        for Candidate {
+            objectives,
+            seed,
+            item,
+        } in candidates
+        {
+            writeln!(
+                f,
+                "{:.4}\t{:.4}\t{}\t{}\t{:.3}\t{}\t{}",
+                objectives.avalanche,
+                objectives.distribution,
+                objectives.instructions,
+                objectives.code_bytes,
+                objectives.cycles_per_byte,
+                seed,
+                item
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn objectives(avalanche: f64, instructions: usize, cycles_per_byte: f64) -> Objectives {
+        Objectives {
+            avalanche,
+            distribution: 1.,
+            instructions,
+            code_bytes: instructions * 4,
+            cycles_per_byte,
+        }
+    }
+
+    #[test]
+    fn keeps_non_dominated() {
+        let mut front = Front::default();
+
+        assert!(front.insert(objectives(0.5, 10, 3.), 0, "a"));
+        // better avalanche but more instructions, so both are kept
+        assert!(front.insert(objectives(0.9, 20, 3.), 1, "b"));
+        // dominated by a
+        assert!(!front.insert(objectives(0.4, 12, 3.), 2, "c"));
+        // an equal candidate doesn't dominate
+        assert!(front.insert(objectives(0.5, 10, 3.), 3, "d"));
+        // dominates a and d
+        assert!(front.insert(objectives(0.6, 10, 2.), 4, "e"));
+
+        let mut items: Vec<_> = front.candidates.iter().map(|c| c.item).collect();
+        items.sort();
+        assert_eq!(items, ["b", "e"]);
+        assert_eq!(front.to_string().lines().count(), 3);
+    }
+}
@@ -0,0 +1,232 @@
+use super::top::Top;
+use std::fmt;
+
+/// scores a shape by summing the scores of its taggings, but gives up on a shape as soon as even
+/// an optimistic estimate of its total falls below the `k`th best total seen so far
+///
+/// the taggings of a shape are treated as independent samples of the same distribution, so after
+/// a few of them the mean and variance of a tagging's score bound the total of the rest
+pub struct Staged {
+    /// the number of taggings a shape that survives is scored with
+    pub taggings: u64,
+    /// the number of taggings scored before a shape can be rejected
+    pub probe: u64,
+    /// how many standard errors above the mean the optimistic estimate is
+    pub confidence: f64,
+    /// the best totals so far
+    top: Top<()>,
+    pub stats: Stats,
+}
+
+#[derive(Default, Debug)]
+pub struct Stats {
+    /// the number of shapes scored with every tagging
+    pub survived: usize,
+    /// the number of shapes given up on early
+    pub rejected: usize,
+    /// the number of shapes thrown away without scoring, because their taggings were degenerate
+    pub degenerate: usize,
+    /// the number of taggings scored in total
+    pub taggings: u64,
+}
+
+/// the scores of the taggings of a shape that survived
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    /// the number of taggings scored, only less than `Staged::taggings` if the shape kept
+    /// producing degenerate ones
+    pub taggings: u64,
+    pub mean: f64,
+    /// the sample variance of a tagging's score
+    pub variance: f64,
+    pub max: f64,
+    /// the mean scaled up to `Staged::taggings` taggings, which shapes are ranked on
+    pub total: f64,
+}
+
+/// the running mean and variance of a stream of scores (welford's algorithm), and their maximum
+struct Moments {
+    count: u64,
+    mean: f64,
+    sum_squares: f64,
+    max: f64,
+}
+
+impl Default for Moments {
+    fn default() -> Moments {
+        Moments {
+            count: 0,
+            mean: 0.,
+            sum_squares: 0.,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Moments {
+    fn push(&mut self, score: f64) {
+        self.count += 1;
+        let delta = score - self.mean;
+        self.mean += delta / self.count as f64;
+        self.sum_squares += delta * (score - self.mean);
+        self.max = self.max.max(score);
+    }
+
+    fn variance(&self) -> f64 {
+        self.sum_squares / self.count.saturating_sub(1).max(1) as f64
+    }
+
+    fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+}
+
+impl Staged {
+    pub fn new(k: usize, taggings: u64) -> Staged {
+        Staged {
+            taggings,
+            probe: 5,
+            confidence: 3.,
+            top: Top::new(k),
+            stats: Stats::default(),
+        }
+    }
+
+    /// the total a shape has to be able to reach to be worth scoring fully, once `k` shapes have
+    /// been scored
+    pub fn threshold(&self) -> Option<f64> {
+        self.top.threshold()
+    }
+
+    /// sum `score` over `taggings` taggings of a shape and summarize them, or return `None` if the
+    /// shape was rejected before all of them were scored
+    ///
+    /// `score` is called with the index of each tagging, and returns `None` for a degenerate
+    /// tagging, which is skipped in favour of the next index. a shape whose first `probe`
+    /// taggings are all degenerate is thrown away, and one that keeps producing them is scored
+    /// on the mean of the taggings it has after twice as many attempts
+    pub fn score(&mut self, mut score: impl FnMut(u64) -> Option<f64>) -> Option<Summary> {
+        let mut moments = Moments::default();
+        let mut total = 0.;
+
+        for tagging in 0..self.taggings * 2 {
+            if moments.count == self.taggings {
+                break;
+            }
+            let Some(tagging_score) = score(tagging) else {
+                if moments.count == 0 && tagging + 1 >= self.probe {
+                    self.stats.degenerate += 1;
+                    return None;
+                }
+                continue;
+            };
+            total += tagging_score;
+            moments.push(tagging_score);
+            self.stats.taggings += 1;
+
+            let remaining = (self.taggings - moments.count) as f64;
+            let optimistic =
+                moments.mean + self.confidence * moments.std_dev() / (moments.count as f64).sqrt();
+
+            match self.threshold() {
+                Some(threshold)
+                    if moments.count >= self.probe
+                        && remaining > 0.
+                        && total + remaining * optimistic < threshold =>
+                {
+                    self.stats.rejected += 1;
+                    return None;
+                }
+                _ => (),
+            }
+        }
+
+        // only less than `taggings` if the shape ran out of attempts
+        let total = moments.mean * self.taggings as f64;
+        self.stats.survived += 1;
+        self.top.insert(total, ());
+        Some(Summary {
+            taggings: moments.count,
+            mean: moments.mean,
+            variance: moments.variance(),
+            max: moments.max,
+            total,
+        })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shapes = self.survived + self.rejected + self.degenerate;
+        write!(
+            f,
+            "{} of {} shapes rejected early and {} as degenerate, {:.1} taggings scored per shape",
+            self.rejected,
+            shapes,
+            self.degenerate,
+            self.taggings as f64 / shapes.max(1) as f64
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn rejects_bad_shapes_early() {
+        let mut rng = thread_rng();
+        let mut staged = Staged::new(5, 100);
+
+        // every shape is better than the last, so none of them are rejected
+        for shape in 0..10 {
+            let good = staged.score(|_| Some(10. + shape as f64 + rng.gen::<f64>()));
+            assert!(good.is_some());
+        }
+
+        let mut scored = 0;
+        let bad = staged.score(|_| {
+            scored += 1;
+            Some(1. + rng.gen::<f64>())
+        });
+
+        assert_eq!(bad, None);
+        assert_eq!(scored, staged.probe);
+        assert!(staged.threshold().unwrap() > 1000.);
+
+        // a shape that is as good as the best survives and gets every tagging
+        assert!(staged.score(|_| Some(20.)).is_some());
+        assert_eq!(staged.stats.survived, 11);
+
+        // degenerate taggings are skipped, unless there's nothing else
+        let summary = staged
+            .score(|tagging| (tagging % 2 == 0).then_some(30.))
+            .unwrap();
+        assert_eq!(summary.total, 3000.);
+        assert_eq!(
+            (summary.mean, summary.variance, summary.max),
+            (30., 0., 30.)
+        );
+        assert_eq!(staged.score(|_| None), None);
+        assert_eq!(staged.stats.degenerate, 1);
+    }
+
+    #[test]
+    fn empty_and_single_moments() {
+        let mut moments = Moments::default();
+        assert_eq!((moments.mean, moments.variance()), (0., 0.));
+
+        moments.push(2.);
+        assert_eq!(
+            (moments.mean, moments.variance(), moments.max),
+            (2., 0., 2.)
+        );
+
+        moments.push(4.);
+        assert_eq!(
+            (moments.mean, moments.variance(), moments.max),
+            (3., 2., 4.)
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use super::bfs::Search;
+use crate::bytecode::code::{Instruction, Program, Value};
+use crate::expr::expr::{Expr, Operator, Tag};
+use crate::expr::linear::has_inputs;
+use crate::hash::Hash;
+use crate::sat::{self, Blast, Equivalence};
+use crate::seed;
+use crate::ssa::code::BinOp;
+use rand::Rng;
+use std::collections::HashSet;
+
+/// the number of inputs every candidate is evaluated on before the exact check
+const FINGERPRINT: usize = 16;
+/// the seed of the random inputs of the fingerprint
+const FINGERPRINT_SEED: u64 = 0;
+
+type Fingerprint = [u64; FINGERPRINT];
+
+/// a function of the hash state and a byte to find the smallest expression for
+pub trait Target: Blast {
+    fn eval(&self, state: u64, byte: u8) -> u64;
+
+    /// the constants the target is built from, which seed the constants candidates are tagged
+    /// with
+    fn constants(&self) -> Vec<u64>;
+}
+
+#[derive(Default)]
+pub struct Report {
+    /// the smallest equivalent expression, if there is one within the budget
+    pub found: Option<Expr<Tag>>,
+    /// the number of candidates that were fingerprinted
+    pub candidates: usize,
+    /// the number of candidates that matched the fingerprint but not the target
+    pub refuted: usize,
+}
+
+/// a tagging of a subtree of a shape and its values on the fingerprint inputs
+struct Tagged {
+    expr: Expr<Tag>,
+    fingerprint: Fingerprint,
+    has_inputs: bool,
+}
+
+/// find the smallest expression with at most `max_leaves` leaves that computes the same function
+/// as the target, trying at most `budget` candidates
+///
+/// shapes come from `Search` in order of size, so the result is the smallest in the shapes the
+/// search explores, and their leaves are tagged with the state, the byte or a constant from
+/// `pool`. Candidates are compared with the target on a few inputs, and
+/// the ones that match are checked exactly with `sat::equivalent`, whose counterexamples are
+/// checked before it from then on
+pub fn superoptimize<T: Target>(target: &T, max_leaves: usize, budget: usize) -> Report {
+    let pool = pool(&target.constants());
+    let inputs = inputs();
+    let expected = inputs.map(|(state, byte)| target.eval(state, byte));
+    let leaves = leaves(&pool, &inputs);
+
+    let mut report = Report::default();
+    let mut counterexamples = Vec::new();
+    let mut matches = |candidate: &Expr<Tag>, report: &mut Report| {
+        let refuted = counterexamples
+            .iter()
+            .any(|(state, byte, hash)| candidate.hash_bytes(*state, &[*byte]) != *hash);
+        if refuted {
+            return false;
+        }
+
+        match sat::equivalent(candidate, target) {
+            Equivalence::Equivalent => true,
+            Equivalence::Counterexample { state, byte } => {
+                report.refuted += 1;
+                counterexamples.push((state, byte, target.eval(state, byte)));
+                false
+            }
+        }
+    };
+
+    for shape in Search::default().take_while(|shape| shape.len() <= max_leaves) {
+        let (a, b, op, bin_op) = match &shape {
+            Expr::Add(a, b) => (a, b, Expr::Add as Operator, BinOp::Add),
+            Expr::Xor(a, b) => (a, b, Expr::Xor as Operator, BinOp::Xor),
+            Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator, BinOp::RotLeft),
+            Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator, BinOp::RotRight),
+            Expr::Tag(()) => {
+                for leaf in &leaves {
+                    if report.candidates == budget {
+                        return report;
+                    }
+                    report.candidates += 1;
+                    if leaf.fingerprint == expected && matches(&leaf.expr, &mut report) {
+                        report.found = Some(leaf.expr.clone());
+                        return report;
+                    }
+                }
+                continue;
+            }
+        };
+
+        // the root is enumerated lazily, only its subtrees are kept
+        let (left, right) = (taggings(a, &leaves), taggings(b, &leaves));
+        for a in &left {
+            for b in right.iter().filter(|b| a.has_inputs || b.has_inputs) {
+                if report.candidates == budget {
+                    return report;
+                }
+                report.candidates += 1;
+
+                let agrees = (0..FINGERPRINT).all(|idx| {
+                    bin_op.apply(a.fingerprint[idx], b.fingerprint[idx]) == expected[idx]
+                });
+                if !agrees {
+                    continue;
+                }
+
+                let candidate = op(Box::new(a.expr.clone()), Box::new(b.expr.clone()));
+                if matches(&candidate, &mut report) {
+                    report.found = Some(candidate);
+                    return report;
+                }
+            }
+        }
+    }
+    report
+}
+
+/// the taggings of a shape with distinct fingerprints, skipping ones with an operation on two
+/// constants, which is the same as a smaller tagging with their result if it is in the pool
+///
+/// a tagging with the fingerprint of an earlier one almost always computes the same function,
+/// so keeping only the first prunes the subtrees of most candidates that can't be new
+fn taggings(shape: &Expr<()>, leaves: &[Tagged]) -> Vec<Tagged> {
+    let (a, b, op, bin_op) = match shape {
+        Expr::Add(a, b) => (a, b, Expr::Add as Operator, BinOp::Add),
+        Expr::Xor(a, b) => (a, b, Expr::Xor as Operator, BinOp::Xor),
+        Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator, BinOp::RotLeft),
+        Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator, BinOp::RotRight),
+        Expr::Tag(()) => {
+            return leaves
+                .iter()
+                .map(|leaf| Tagged {
+                    expr: leaf.expr.clone(),
+                    ..*leaf
+                })
+                .collect()
+        }
+    };
+
+    let (left, right) = (taggings(a, leaves), taggings(b, leaves));
+    let mut tagged = Vec::new();
+    let mut seen = HashSet::new();
+    for a in &left {
+        for b in right.iter().filter(|b| a.has_inputs || b.has_inputs) {
+            let fingerprint =
+                std::array::from_fn(|idx| bin_op.apply(a.fingerprint[idx], b.fingerprint[idx]));
+            if seen.insert(fingerprint) {
+                tagged.push(Tagged {
+                    expr: op(Box::new(a.expr.clone()), Box::new(b.expr.clone())),
+                    fingerprint,
+                    has_inputs: true,
+                });
+            }
+        }
+    }
+    tagged
+}
+
+fn leaves(pool: &[u64], inputs: &[(u64, u8); FINGERPRINT]) -> Vec<Tagged> {
+    [Tag::HashState, Tag::Byte]
+        .into_iter()
+        .chain(pool.iter().map(|num| Tag::Const(*num)))
+        .map(|tag| {
+            let expr = Expr::Tag(tag);
+            Tagged {
+                fingerprint: inputs.map(|(state, byte)| expr.hash_bytes(state, &[byte])),
+                has_inputs: has_inputs(&expr),
+                expr,
+            }
+        })
+        .collect()
+}
+
+/// the constants of the target, and their sums, xors and rotations by each other, which is
+/// enough to fold any two constants of the target into one
+fn pool(constants: &[u64]) -> Vec<u64> {
+    let mut pool = constants.to_vec();
+    for a in constants {
+        for b in constants {
+            pool.extend([
+                a.wrapping_add(*b),
+                a ^ b,
+                a.rotate_left(*b as u32),
+                a.rotate_right(*b as u32),
+            ]);
+        }
+    }
+
+    pool.sort_unstable();
+    pool.dedup();
+    pool
+}
+
+/// the corners of the input space and random inputs
+fn inputs() -> [(u64, u8); FINGERPRINT] {
+    let mut rng = seed::rng(FINGERPRINT_SEED);
+    let mut inputs = [(0, 0), (u64::MAX, u8::MAX), (1, 1), (1 << 63, 0x80)].into_iter();
+    std::array::from_fn(|_| inputs.next().unwrap_or_else(|| (rng.gen(), rng.gen())))
+}
+
+impl Target for Expr<Tag> {
+    fn eval(&self, state: u64, byte: u8) -> u64 {
+        self.hash_bytes(state, &[byte])
+    }
+
+    /// the values of the largest subexpressions that don't depend on the state or the byte
+    fn constants(&self) -> Vec<u64> {
+        match self {
+            _ if !has_inputs(self) => vec![self.eval(0, 0)],
+            Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+                [a.constants(), b.constants()].concat()
+            }
+            Expr::Tag(_) => Vec::new(),
+        }
+    }
+}
+
+impl Target for Program {
+    fn eval(&self, state: u64, byte: u8) -> u64 {
+        Program::eval(self, state, byte)
+    }
+
+    fn constants(&self) -> Vec<u64> {
+        let mut constants: Vec<u64> = self
+            .instructions
+            .iter()
+            .filter_map(|instr| match instr {
+                Instruction::MoveAbs(_, num) => Some(*num),
+                Instruction::Move(_, Value::Immediate(num))
+                | Instruction::Add(_, Value::Immediate(num))
+                | Instruction::Xor(_, Value::Immediate(num))
+                | Instruction::RotLeft(_, Value::Immediate(num))
+                | Instruction::RotRight(_, Value::Immediate(num)) => Some(u64::from(*num)),
+                _ => None,
+            })
+            .collect();
+        if let Value::Immediate(num) = self.result {
+            constants.push(u64::from(num));
+        }
+        constants
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::parse;
+
+    #[test]
+    fn finds_smaller_expressions() {
+        let cases = [
+            ("((state + 5) + (byte + 7))", "((state + byte) + 12)"),
+            ("((state xor byte) xor state)", "byte"),
+            ("((state << 13) << 60)", "(state << 9)"),
+            (
+                "(((state xor 3) xor byte) + (state xor state))",
+                "((byte xor 3) xor state)",
+            ),
+        ];
+
+        for (text, smallest) in cases {
+            let target = parse(text).unwrap();
+            let report = superoptimize(&target, target.len() - 1, 1_000_000);
+
+            let found = report
+                .found
+                .unwrap_or_else(|| panic!("nothing smaller than {}", text));
+            assert_eq!(
+                found.len(),
+                parse(smallest).unwrap().len(),
+                "{} gave {}",
+                text,
+                found
+            );
+            assert_eq!(sat::equivalent(&found, &target), Equivalence::Equivalent);
+        }
+    }
+
+    #[test]
+    fn smallest_is_not_improved() {
+        let target = parse("((state xor byte) + (state << 8))").unwrap();
+        let report = superoptimize(&target, target.len() - 1, 1_000_000);
+        assert!(report.found.is_none());
+        assert!(report.candidates > 0);
+    }
+
+    #[test]
+    fn budget_is_a_hard_limit() {
+        let target = parse("((state xor byte) + (state << 8))").unwrap();
+
+        // the first budgets run out among the leaves, the last among the larger shapes
+        for budget in [0, 1, 3, 1000] {
+            let report = superoptimize(&target, target.len() - 1, budget);
+            assert!(report.found.is_none());
+            assert_eq!(report.candidates, budget);
+        }
+    }
+}
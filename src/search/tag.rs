@@ -1,42 +1,354 @@
+use crate::expr::analysis::degeneracy;
 use crate::expr::expr::{Expr, Operator, Tag};
-use rand::{rngs::ThreadRng, thread_rng, Rng};
+use rand::Rng;
+use std::collections::HashMap;
 
+/// the number of times a degenerate tagging is thrown away and tagged again
+const RETAGS: usize = 8;
+/// the number of scores a kind of tag needs at a position before it is favoured or avoided there
+const MIN_SAMPLES: u64 = 8;
+/// positions deeper than this share their statistics
+const MAX_DEPTH: usize = 3;
+
+/// the kinds of tags the tagger learns the odds of, constants by the range they fall in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Byte,
+    HashState,
+    /// a constant below 64, like a rotation amount
+    Small,
+    /// a constant that fits in 32 bits
+    Word,
+    Wide,
+}
+
+const KINDS: [Kind; 5] = [
+    Kind::Byte,
+    Kind::HashState,
+    Kind::Small,
+    Kind::Word,
+    Kind::Wide,
+];
+/// the odds of each kind before anything is learned, the byte and the state a quarter of the
+/// time each and a uniformly random constant otherwise, which is almost never small or a word
+const PRIOR: [f64; 5] = [
+    0.25,
+    0.25,
+    0.5 * 64. / U64_VALUES,
+    0.5 * (U32_VALUES - 64.) / U64_VALUES,
+    0.5 * (U64_VALUES - U32_VALUES) / U64_VALUES,
+];
+/// the number of values of a u32 and a u64
+const U32_VALUES: f64 = (1u64 << 32) as f64;
+const U64_VALUES: f64 = (1u128 << 64) as f64;
+
+impl Kind {
+    fn of(tag: &Tag) -> Kind {
+        match tag {
+            Tag::Byte => Kind::Byte,
+            Tag::HashState => Kind::HashState,
+            Tag::Const(num) if *num < 64 => Kind::Small,
+            Tag::Const(num) if *num <= u64::from(u32::MAX) => Kind::Word,
+            Tag::Const(_) => Kind::Wide,
+        }
+    }
+
+    fn sample<R: Rng>(self, rng: &mut R) -> Tag {
+        match self {
+            Kind::Byte => Tag::Byte,
+            Kind::HashState => Tag::HashState,
+            Kind::Small => Tag::Const(rng.gen_range(0..64)),
+            Kind::Word => Tag::Const(rng.gen_range(64..=u64::from(u32::MAX))),
+            Kind::Wide => Tag::Const(rng.gen_range(u64::from(u32::MAX) + 1..=u64::MAX)),
+        }
+    }
+}
+
+/// where a leaf is in its shape: the operator it is an operand of (`None` for a shape that is a
+/// single leaf), which operand it is, and how deep it is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Position {
+    parent: Option<usize>,
+    right: bool,
+    depth: usize,
+}
+
+/// the running mean of the scores of the taggings with each kind of tag at a position, and the
+/// mean and variance of all of them (welford's algorithm)
 #[derive(Default)]
-pub struct Tagger;
+struct Stats {
+    count: u64,
+    mean: f64,
+    sum_squares: f64,
+    kinds: [(u64, f64); 5],
+}
+
+impl Stats {
+    fn push(&mut self, kind: Kind, score: f64) {
+        self.count += 1;
+        let delta = score - self.mean;
+        self.mean += delta / self.count as f64;
+        self.sum_squares += delta * (score - self.mean);
 
-pub struct TagState {
-    rng: ThreadRng,
+        let (count, mean) = &mut self.kinds[kind as usize];
+        *count += 1;
+        *mean += (score - *mean) / *count as f64;
+    }
+
+    /// the odds of each kind, the prior weighted by how many standard deviations better than
+    /// the average tagging the kind has scored at this position, over `exploration`
+    fn weights(&self, exploration: f64) -> [f64; 5] {
+        let std_dev = (self.sum_squares / (self.count - 1).max(1) as f64).sqrt();
+        std::array::from_fn(|kind| {
+            let (count, mean) = self.kinds[kind];
+            let advantage = if count >= MIN_SAMPLES && std_dev > 0. {
+                (mean - self.mean) / std_dev
+            } else {
+                0.
+            };
+            PRIOR[kind] * (advantage / exploration).exp()
+        })
+    }
+}
+
+/// tags the leaves of shapes, learning from the scores of its taggings which kinds of tags do
+/// well at which positions
+///
+/// `exploration` is how far the odds stay from the fixed prior, a tag kind that scores one
+/// standard deviation better than average at a position is `e^(1 / exploration)` times as likely
+/// there, so the prior odds are kept with an infinite `exploration` and a small one is close to
+/// always picking the best kind
+pub struct Tagger {
+    pub exploration: f64,
+    stats: HashMap<Position, Stats>,
 }
 
-impl TagState {
+impl Default for Tagger {
+    fn default() -> Tagger {
+        Tagger {
+            exploration: 1.,
+            stats: HashMap::new(),
+        }
+    }
+}
+
+pub struct TagState<'r, R: Rng> {
+    rng: &'r mut R,
+    tagger: &'r Tagger,
+}
+
+impl<R: Rng> TagState<'_, R> {
+    /// tag the leaves of a shape, tagging it again while `expr::analysis` finds the tagging
+    /// degenerate, the result can still be degenerate if the shape can't be tagged any other way
     pub fn annotate(&mut self, e: &Expr<()>) -> Expr<Tag> {
-        let (a, b, op) = match e {
-            Expr::Add(a, b) => (a, b, Expr::Add as Operator),
-            Expr::Xor(a, b) => (a, b, Expr::Xor as Operator),
-            Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator),
-            Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator),
-            Expr::Tag(()) => return Expr::Tag(self.rand_tag()),
+        let mut tagged = self.tag(e, None, false, 0);
+        for _ in 0..RETAGS {
+            if degeneracy(&tagged).is_none() {
+                break;
+            }
+            tagged = self.tag(e, None, false, 0);
+        }
+        tagged
+    }
+
+    fn tag(&mut self, e: &Expr<()>, parent: Option<usize>, right: bool, depth: usize) -> Expr<Tag> {
+        let (a, b, op, idx) = match e {
+            Expr::Add(a, b) => (a, b, Expr::Add as Operator, 0),
+            Expr::Xor(a, b) => (a, b, Expr::Xor as Operator, 1),
+            Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator, 2),
+            Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator, 3),
+            Expr::Tag(()) => {
+                let position = Position {
+                    parent,
+                    right,
+                    depth: depth.min(MAX_DEPTH),
+                };
+                return Expr::Tag(self.rand_tag(position));
+            }
         };
-        op(Box::new(self.annotate(a)), Box::new(self.annotate(b)))
+        op(
+            Box::new(self.tag(a, Some(idx), false, depth + 1)),
+            Box::new(self.tag(b, Some(idx), true, depth + 1)),
+        )
     }
 
-    fn rand_tag(&mut self) -> Tag {
-        match self.rng.gen::<u8>() % 4 {
-            0 => Tag::Byte,
-            1 => Tag::HashState,
-            2..=u8::MAX => Tag::Const(self.rng.gen()),
+    fn rand_tag(&mut self, position: Position) -> Tag {
+        let weights = self
+            .tagger
+            .stats
+            .get(&position)
+            .map_or(PRIOR, |stats| stats.weights(self.tagger.exploration));
+        let mut pick = self.rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for (kind, weight) in KINDS.into_iter().zip(weights) {
+            if pick < weight {
+                return kind.sample(self.rng);
+            }
+            pick -= weight;
         }
+        Kind::Wide.sample(self.rng)
     }
 }
 
 impl Tagger {
-    pub fn annotate(&self, e: &Expr<()>) -> Expr<Tag> {
-        self.new_tag_state().annotate(e)
+    pub fn annotate<R: Rng>(&self, e: &Expr<()>, rng: &mut R) -> Expr<Tag> {
+        self.new_tag_state(rng).annotate(e)
     }
 
-    pub fn feedback(&mut self, raw: &Expr<()>, tagged: &Expr<Tag>, score: usize) {}
+    /// learn from the score of a tagging of a shape, the higher the better
+    pub fn feedback(&mut self, raw: &Expr<()>, tagged: &Expr<Tag>, score: f64) {
+        self.learn(raw, tagged, score, None, false, 0);
+    }
 
-    pub fn new_tag_state(&self) -> TagState {
-        TagState { rng: thread_rng() }
+    fn learn(
+        &mut self,
+        raw: &Expr<()>,
+        tagged: &Expr<Tag>,
+        score: f64,
+        parent: Option<usize>,
+        right: bool,
+        depth: usize,
+    ) {
+        let ((a, b), (tagged_a, tagged_b), idx) = match (raw, tagged) {
+            (Expr::Add(a, b), Expr::Add(c, d)) => ((a, b), (c, d), 0),
+            (Expr::Xor(a, b), Expr::Xor(c, d)) => ((a, b), (c, d), 1),
+            (Expr::RotLeft(a, b), Expr::RotLeft(c, d)) => ((a, b), (c, d), 2),
+            (Expr::RotRight(a, b), Expr::RotRight(c, d)) => ((a, b), (c, d), 3),
+            (Expr::Tag(()), Expr::Tag(tag)) => {
+                let position = Position {
+                    parent,
+                    right,
+                    depth: depth.min(MAX_DEPTH),
+                };
+                self.stats
+                    .entry(position)
+                    .or_default()
+                    .push(Kind::of(tag), score);
+                return;
+            }
+            _ => panic!("the tagging doesn't have the shape it was fed back with"),
+        };
+        self.learn(a, tagged_a, score, Some(idx), false, depth + 1);
+        self.learn(b, tagged_b, score, Some(idx), true, depth + 1);
+    }
+
+    /// a tag for a single leaf, with the odds learned for its position: the `right` or left
+    /// operand of the operator `parent` indexes (add, xor, rotate left and rotate right in that
+    /// order, `None` for a leaf that is the whole expression) at `depth`
+    pub fn tag_leaf<R: Rng>(
+        &self,
+        parent: Option<usize>,
+        right: bool,
+        depth: usize,
+        rng: &mut R,
+    ) -> Tag {
+        let position = Position {
+            parent,
+            right,
+            depth: depth.min(MAX_DEPTH),
+        };
+        self.new_tag_state(rng).rand_tag(position)
+    }
+
+    pub fn new_tag_state<'r, R: Rng>(&'r self, rng: &'r mut R) -> TagState<'r, R> {
+        TagState { rng, tagger: self }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::parse_shape;
+    use rand::prelude::*;
+
+    /// the fraction of the leaves of taggings of a shape that are of a kind
+    fn frequency(tagger: &Tagger, shape: &Expr<()>, kind: Kind, rng: &mut ThreadRng) -> f64 {
+        let mut leaves = Vec::new();
+        for _ in 0..2000 {
+            let tagged = tagger.new_tag_state(rng).tag(shape, None, false, 0);
+            collect_kinds(&tagged, &mut leaves);
+        }
+        leaves.iter().filter(|leaf| **leaf == kind).count() as f64 / leaves.len() as f64
+    }
+
+    fn collect_kinds(expr: &Expr<Tag>, out: &mut Vec<Kind>) {
+        match expr {
+            Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+                collect_kinds(a, out);
+                collect_kinds(b, out);
+            }
+            Expr::Tag(tag) => out.push(Kind::of(tag)),
+        }
+    }
+
+    #[test]
+    fn learns_which_tags_score_well() {
+        let mut rng = thread_rng();
+        let shape = parse_shape("((_ xor _) << (_ + _))").unwrap();
+        let mut tagger = Tagger::default();
+        assert!((frequency(&tagger, &shape, Kind::Byte, &mut rng) - 0.25).abs() < 0.02);
+
+        // rotating by the byte is rewarded, and nothing else matters
+        for _ in 0..500 {
+            let tagged = tagger.annotate(&shape, &mut rng);
+            let score = match &tagged {
+                Expr::RotLeft(_, amount) => match **amount {
+                    Expr::Add(ref a, ref b) => {
+                        let mut kinds = Vec::new();
+                        collect_kinds(a, &mut kinds);
+                        collect_kinds(b, &mut kinds);
+                        kinds.iter().filter(|kind| **kind == Kind::Byte).count() as f64
+                    }
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+            tagger.feedback(&shape, &tagged, score + rng.gen::<f64>());
+        }
+
+        let amount = Position {
+            parent: Some(0),
+            right: false,
+            depth: 2,
+        };
+        let weights = tagger.stats[&amount].weights(tagger.exploration);
+        let odds = weights[Kind::Byte as usize] / weights.iter().sum::<f64>();
+        assert!(odds > 0.4, "{}", odds);
+        let learned = frequency(&tagger, &shape, Kind::Byte, &mut rng);
+        assert!(learned > 0.3, "{}", learned);
+
+        // a single leaf is tagged with the odds of its position too
+        let bytes = (0..1000)
+            .filter(|_| matches!(tagger.tag_leaf(Some(0), false, 2, &mut rng), Tag::Byte))
+            .count();
+        assert!(bytes > 400, "{}", bytes);
+
+        let amount = parse_shape("(_ + _)").unwrap();
+
+        // positions that weren't learned about keep the prior, and so does an infinite exploration
+        let other = frequency(&tagger, &amount, Kind::Byte, &mut rng);
+        assert!((other - 0.25).abs() < 0.02, "{}", other);
+        tagger.exploration = f64::INFINITY;
+        let prior = frequency(&tagger, &shape, Kind::Byte, &mut rng);
+        assert!((prior - 0.25).abs() < 0.02, "{}", prior);
+    }
+
+    #[test]
+    fn constants_are_uniform_by_default() {
+        let mut rng = thread_rng();
+        let shape = parse_shape("((_ xor _) << (_ + _))").unwrap();
+        let tagger = Tagger::default();
+
+        assert!((frequency(&tagger, &shape, Kind::Wide, &mut rng) - 0.5).abs() < 0.02);
+        assert_eq!(frequency(&tagger, &shape, Kind::Small, &mut rng), 0.);
+    }
+
+    #[test]
+    fn kinds_sample_their_range() {
+        let mut rng = thread_rng();
+        for kind in KINDS {
+            for _ in 0..100 {
+                assert_eq!(Kind::of(&kind.sample(&mut rng)), kind);
+            }
+        }
     }
 }
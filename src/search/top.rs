@@ -0,0 +1,72 @@
+/// the `k` best scoring items seen so far
+pub struct Top<T> {
+    k: usize,
+    /// in ascending order of score
+    entries: Vec<(f64, T)>,
+}
+
+impl<T> Top<T> {
+    pub fn new(k: usize) -> Top<T> {
+        Top {
+            k,
+            entries: Vec::with_capacity(k + 1),
+        }
+    }
+
+    /// the score an item has to beat to be kept, once there are `k` of them, there is none when
+    /// nothing is kept
+    pub fn threshold(&self) -> Option<f64> {
+        self.entries
+            .first()
+            .filter(|_| self.entries.len() == self.k)
+            .map(|(score, _)| *score)
+    }
+
+    pub fn insert(&mut self, score: f64, item: T) {
+        if self.threshold().is_some_and(|threshold| score <= threshold) {
+            return;
+        }
+        let idx = self.entries.partition_point(|(other, _)| *other < score);
+        self.entries.insert(idx, (score, item));
+        if self.entries.len() > self.k {
+            self.entries.remove(0);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// the items, best first
+    pub fn iter(&self) -> impl Iterator<Item = &(f64, T)> {
+        self.entries.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn keeps_the_best() {
+        let mut rng = thread_rng();
+        let scores: Vec<f64> = (0..1000).map(|_| rng.gen()).collect();
+        let mut top = Top::new(10);
+        for (idx, score) in scores.iter().enumerate() {
+            top.insert(*score, idx);
+        }
+
+        let mut sorted = scores.clone();
+        sorted.sort_by(|a, b| b.total_cmp(a));
+        let kept: Vec<f64> = top.iter().map(|(score, _)| *score).collect();
+        assert_eq!(kept, sorted[..10]);
+        assert_eq!(top.threshold(), Some(sorted[9]));
+        assert!(top.iter().all(|(score, idx)| scores[*idx] == *score));
+
+        let mut none = Top::new(0);
+        assert_eq!(none.threshold(), None);
+        none.insert(1., ());
+        assert_eq!(none.len(), 0);
+    }
+}
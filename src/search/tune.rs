@@ -0,0 +1,236 @@
+use crate::expr::expr::{Expr, Operator, Tag};
+use rand::Rng;
+
+/// the score of the current and the best constants after an iteration of a tuning run
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    pub iteration: usize,
+    pub score: f64,
+    pub best: f64,
+}
+
+pub struct Tuned {
+    /// the expression with the best constants found
+    pub expr: Expr<Tag>,
+    pub score: f64,
+    /// a step for every iteration, the first one being the score of the expression tuned
+    pub trace: Vec<Step>,
+}
+
+/// tunes the constants of a tagged expression by simulated annealing, leaving its shape and its
+/// state and byte leaves alone
+///
+/// a move flips a few bits of a constant, or nudges a rotation amount by one or replaces it. A
+/// worse move is accepted with probability `exp(delta / temperature)`, and the temperature falls
+/// linearly to 0 over every restart, which starts again from the best constants so far, so the
+/// end of every restart is a hill climb
+pub struct Tuner {
+    /// the number of moves scored in total
+    pub iterations: usize,
+    pub restarts: usize,
+    /// the temperature at the start of every restart, in units of the score
+    pub temperature: f64,
+}
+
+impl Tuner {
+    pub fn new(iterations: usize) -> Tuner {
+        Tuner {
+            iterations,
+            restarts: 4,
+            temperature: 0.1,
+        }
+    }
+
+    pub fn tune<R: Rng>(
+        &self,
+        expr: &Expr<Tag>,
+        rng: &mut R,
+        mut score: impl FnMut(&Expr<Tag>) -> f64,
+    ) -> Tuned {
+        let mut constants = constants(expr);
+        let mut current = score(expr);
+        let mut best = (constants.clone(), current);
+        let mut trace = vec![Step {
+            iteration: 0,
+            score: current,
+            best: current,
+        }];
+        if constants.is_empty() {
+            return Tuned {
+                expr: expr.clone(),
+                score: current,
+                trace,
+            };
+        }
+
+        let per_restart = self.iterations.div_ceil(self.restarts.max(1));
+        for iteration in 1..=self.iterations {
+            let progress = ((iteration - 1) % per_restart) as f64 / per_restart as f64;
+            if progress == 0. {
+                (constants, current) = best.clone();
+            }
+            let temperature = self.temperature * (1. - progress);
+
+            let mut moved = constants.clone();
+            let idx = rng.gen_range(0..moved.len());
+            moved[idx].perturb(rng);
+            let moved_score = score(&with_constants(expr, &moved));
+
+            // a move that scores NaN or infinity is rejected, and any other one is better than a
+            // current score like that
+            let delta = moved_score - current;
+            let accepted = moved_score.is_finite()
+                && (!current.is_finite()
+                    || delta >= 0.
+                    || (temperature > 0. && rng.gen_bool((delta / temperature).exp())));
+            if accepted {
+                (constants, current) = (moved, moved_score);
+                if current > best.1 || !best.1.is_finite() {
+                    best = (constants.clone(), current);
+                }
+            }
+            trace.push(Step {
+                iteration,
+                score: current,
+                best: best.1,
+            });
+        }
+
+        Tuned {
+            expr: with_constants(expr, &best.0),
+            score: best.1,
+            trace,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Constant {
+    num: u64,
+    /// whether the constant is the amount of a rotation, so only its low 6 bits matter
+    rotation: bool,
+}
+
+impl Constant {
+    fn perturb<R: Rng>(&mut self, rng: &mut R) {
+        self.num = match (self.rotation, rng.gen_range(0..4)) {
+            (true, 0) => rng.gen_range(0..64),
+            (true, _) if rng.gen() => self.num.wrapping_add(1) & 63,
+            (true, _) => self.num.wrapping_sub(1) & 63,
+            (false, 0) => rng.gen(),
+            (false, _) => {
+                (0..rng.gen_range(1..=3)).fold(self.num, |num, _| num ^ 1 << rng.gen_range(0..64))
+            }
+        };
+    }
+}
+
+/// the constant leaves of an expression, in order
+fn constants(expr: &Expr<Tag>) -> Vec<Constant> {
+    fn collect(expr: &Expr<Tag>, rotation: bool, out: &mut Vec<Constant>) {
+        match expr {
+            Expr::Add(a, b) | Expr::Xor(a, b) => {
+                collect(a, false, out);
+                collect(b, false, out);
+            }
+            Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+                collect(a, false, out);
+                collect(b, true, out);
+            }
+            Expr::Tag(Tag::Const(num)) => out.push(Constant {
+                num: *num,
+                rotation,
+            }),
+            Expr::Tag(_) => (),
+        }
+    }
+
+    let mut out = Vec::new();
+    collect(expr, false, &mut out);
+    out
+}
+
+/// the expression with its constant leaves replaced, in order
+fn with_constants(expr: &Expr<Tag>, constants: &[Constant]) -> Expr<Tag> {
+    fn rebuild<'c>(
+        expr: &Expr<Tag>,
+        constants: &mut impl Iterator<Item = &'c Constant>,
+    ) -> Expr<Tag> {
+        let (a, b, op) = match expr {
+            Expr::Add(a, b) => (a, b, Expr::Add as Operator),
+            Expr::Xor(a, b) => (a, b, Expr::Xor as Operator),
+            Expr::RotLeft(a, b) => (a, b, Expr::RotLeft as Operator),
+            Expr::RotRight(a, b) => (a, b, Expr::RotRight as Operator),
+            Expr::Tag(Tag::Const(_)) => {
+                return Expr::Tag(Tag::Const(constants.next().unwrap().num))
+            }
+            Expr::Tag(tag) => return Expr::Tag(tag.clone()),
+        };
+        let a = rebuild(a, constants);
+        op(Box::new(a), Box::new(rebuild(b, constants)))
+    }
+
+    rebuild(expr, &mut constants.iter())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::parse;
+    use rand::prelude::*;
+
+    #[test]
+    fn only_constants_change() {
+        let (mut rng, mut scores) = (thread_rng(), thread_rng());
+
+        for _ in 0..50 {
+            let expr = Expr::rand(&mut rng);
+            let tuned = Tuner::new(20).tune(&expr, &mut rng, |_| scores.gen());
+
+            assert!(tuned.expr.shape() == expr.shape(), "{}", expr);
+            assert_eq!(
+                tuned.expr.to_string().replace(char::is_numeric, ""),
+                expr.to_string().replace(char::is_numeric, "")
+            );
+            assert_eq!(tuned.score, tuned.trace.last().unwrap().best);
+            assert!(tuned.trace.windows(2).all(|w| w[1].best >= w[0].best));
+        }
+    }
+
+    #[test]
+    fn rejects_non_finite_scores() {
+        let mut rng = thread_rng();
+        let expr = parse("((state + 12345) << 3)").unwrap();
+
+        for first in [f64::NAN, f64::INFINITY, 0.] {
+            let mut scores = thread_rng();
+            let mut calls = 0;
+            let tuned = Tuner::new(200).tune(&expr, &mut rng, |_| {
+                calls += 1;
+                match (calls, scores.gen_range(0..3)) {
+                    (1, _) => first,
+                    (_, 0) => f64::NAN,
+                    (_, 1) => f64::NEG_INFINITY,
+                    _ => scores.gen(),
+                }
+            });
+            assert!(tuned.score.is_finite(), "{}", tuned.score);
+        }
+    }
+
+    #[test]
+    fn finds_constants() {
+        let expr = parse("((state + 12345) << 3)").unwrap();
+        let target = |expr: &Expr<Tag>| {
+            let (left, amount) = match constants(expr)[..] {
+                [ref left, ref amount] => (left.num, amount.num),
+                _ => unreachable!(),
+            };
+            -f64::from((left ^ 0xdead_beef).count_ones()) - (amount as f64 - 41.).abs()
+        };
+
+        let tuned = Tuner::new(20_000).tune(&expr, &mut thread_rng(), target);
+        assert_eq!(tuned.score, 0., "{}", tuned.expr);
+        assert_eq!(tuned.expr.to_string(), "((state + 3735928559) << 41)");
+    }
+}
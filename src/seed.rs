@@ -0,0 +1,15 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// derive an independent seed from a seed and an index, so that every candidate of a run can be
+/// reproduced on its own, using the splitmix64 finalizer
+pub fn derive(seed: u64, idx: u64) -> u64 {
+    let mut z = seed.wrapping_add(idx.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
@@ -0,0 +1,148 @@
+use super::code::{BinOp, Function, Instruction, Var};
+
+/// find which variables contribute to the result of a function
+pub fn live(func: &Function) -> Vec<bool> {
+    let mut live = vec![false; func.len()];
+    live[func.result] = true;
+
+    for (var, instr) in func.instructions.iter().enumerate().rev() {
+        if let (true, Some((a, b))) = (live[var], instr.operands()) {
+            live[a] = true;
+            live[b] = true;
+        }
+    }
+
+    live
+}
+
+/// find the index of the last instruction that reads each variable, ignoring dead instructions
+///
+/// the result is read after the last instruction, at index `func.len()`
+pub fn last_uses(func: &Function) -> Vec<Option<usize>> {
+    let live = live(func);
+    let mut last = vec![None; func.len()];
+
+    for (idx, instr) in func.instructions.iter().enumerate() {
+        if let (true, Some((a, b))) = (live[idx], instr.operands()) {
+            last[a] = Some(idx);
+            last[b] = Some(idx);
+        }
+    }
+    last[func.result] = Some(func.len());
+
+    last
+}
+
+/// dominance between the instructions of a straight line function
+///
+/// without branches, a definition dominates every instruction that comes after it
+pub struct Dominance {
+    position: Vec<usize>,
+}
+
+impl Dominance {
+    pub fn new(func: &Function) -> Self {
+        Dominance {
+            position: (0..func.len()).collect(),
+        }
+    }
+
+    pub fn dominates(&self, a: Var, b: Var) -> bool {
+        self.position[a] <= self.position[b]
+    }
+
+    /// check that every operand is defined before the instruction that uses it
+    pub fn is_valid(&self, func: &Function) -> bool {
+        func.result < func.len()
+            && func.instructions.iter().enumerate().all(|(var, instr)| {
+                instr.operands().is_none_or(|(a, b)| {
+                    a != var && b != var && self.dominates(a, var) && self.dominates(b, var)
+                })
+            })
+    }
+}
+
+/// propagate constants forward through a function, returning the value of each variable that is
+/// the same for every hash state and byte
+pub fn constants(func: &Function) -> Vec<Option<u64>> {
+    let mut known: Vec<Option<u64>> = Vec::with_capacity(func.len());
+
+    for instr in &func.instructions {
+        let val = match *instr {
+            Instruction::HashState | Instruction::Byte => None,
+            Instruction::Const(num) => Some(num),
+            Instruction::Binary(op, a, b) => match (op, known[a], known[b]) {
+                (_, Some(a), Some(b)) => Some(op.apply(a, b)),
+                (BinOp::Xor, _, _) if a == b => Some(0),
+                // rotating all zeroes or all ones is a no-op
+                (BinOp::RotLeft | BinOp::RotRight, Some(num @ (0 | u64::MAX)), _) => Some(num),
+                _ => None,
+            },
+        };
+        known.push(val);
+    }
+
+    known
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::parse::parse;
+
+    fn lower(text: &str) -> Function {
+        Function::from(&parse(text).unwrap())
+    }
+
+    #[test]
+    fn propagates_constants() {
+        // state, 3, 4, add, xor, 0, byte, rotl, add
+        let func = lower("((state xor (3 + 4)) + (0 << byte))");
+        let known = constants(&func);
+
+        assert_eq!(known[3], Some(7));
+        assert_eq!(known[4], None);
+        assert_eq!(known[7], Some(0));
+        assert_eq!(known[func.result], None);
+
+        // lowering doesn't merge the two reads of the state, so only an xor of a variable with
+        // itself is known
+        let func = Function {
+            instructions: vec![
+                Instruction::HashState,
+                Instruction::Binary(BinOp::Xor, 0, 0),
+            ],
+            result: 1,
+        };
+        assert_eq!(constants(&func), [None, Some(0)]);
+    }
+
+    #[test]
+    fn operands_come_first() {
+        let func = lower("((state + byte) xor 5)");
+        let dominance = Dominance::new(&func);
+        assert!(dominance.is_valid(&func));
+        assert!(dominance.dominates(0, 2) && !dominance.dominates(2, 0));
+
+        let mut backwards = func.clone();
+        backwards.instructions[2] = Instruction::Binary(BinOp::Add, 0, 3);
+        assert!(!dominance.is_valid(&backwards));
+
+        let mut itself = func;
+        itself.instructions[2] = Instruction::Binary(BinOp::Add, 0, 2);
+        assert!(!dominance.is_valid(&itself));
+    }
+
+    #[test]
+    fn live_ranges() {
+        let mut func = lower("((state + byte) xor 5)");
+        func.instructions
+            .push(Instruction::Binary(BinOp::Add, 0, 1));
+
+        assert_eq!(live(&func), [true, true, true, true, true, false]);
+        assert_eq!(
+            last_uses(&func),
+            [Some(2), Some(2), Some(4), Some(4), Some(6), None]
+        );
+    }
+}
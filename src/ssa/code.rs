@@ -0,0 +1,174 @@
+use crate::hash::Hash;
+use std::fmt;
+
+/// an ssa variable, which is the index of the instruction that defines it
+pub type Var = usize;
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Xor,
+    RotLeft,
+    RotRight,
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Instruction {
+    HashState,
+    Byte,
+    Const(u64),
+    Binary(BinOp, Var, Var),
+}
+
+/// a straight line function in ssa form
+///
+/// every instruction defines exactly one variable (its own index), and may only refer to
+/// variables defined before it
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub instructions: Vec<Instruction>,
+    pub result: Var,
+}
+
+impl BinOp {
+    pub fn apply(self, a: u64, b: u64) -> u64 {
+        match self {
+            BinOp::Add => a.wrapping_add(b),
+            BinOp::Xor => a ^ b,
+            BinOp::RotLeft => a.rotate_left(b as u32),
+            BinOp::RotRight => a.rotate_right(b as u32),
+        }
+    }
+
+    pub fn is_commutative(self) -> bool {
+        matches!(self, BinOp::Add | BinOp::Xor)
+    }
+
+    pub fn is_rotation(self) -> bool {
+        matches!(self, BinOp::RotLeft | BinOp::RotRight)
+    }
+}
+
+impl Instruction {
+    pub fn operands(&self) -> Option<(Var, Var)> {
+        match self {
+            Instruction::Binary(_, a, b) => Some((*a, *b)),
+            _ => None,
+        }
+    }
+}
+
+impl Function {
+    pub fn eval(&self, hash_state: u64, byte: u8) -> u64 {
+        let mut vars = Vec::with_capacity(self.instructions.len());
+
+        for instr in &self.instructions {
+            let val = match instr {
+                Instruction::HashState => hash_state,
+                Instruction::Byte => byte as u64,
+                Instruction::Const(num) => *num,
+                Instruction::Binary(op, a, b) => op.apply(vars[*a], vars[*b]),
+            };
+            vars.push(val);
+        }
+
+        vars[self.result]
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+}
+
+impl Hash for Function {
+    fn hash_bytes(&self, init: u64, bytes: &[u8]) -> u64 {
+        let mut hash = init;
+        for byte in bytes {
+            hash = self.eval(hash, *byte);
+        }
+        hash
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinOp::Add => write!(f, "add"),
+            BinOp::Xor => write!(f, "xor"),
+            BinOp::RotLeft => write!(f, "rotl"),
+            BinOp::RotRight => write!(f, "rotr"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::HashState => write!(f, "state"),
+            Instruction::Byte => write!(f, "byte"),
+            Instruction::Const(num) => write!(f, "${}", num),
+            Instruction::Binary(op, a, b) => write!(f, "{} v{} v{}", op, a, b),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "v{} where", self.result)?;
+        for (var, instr) in self.instructions.iter().enumerate() {
+            writeln!(f, "v{} = {}", var, instr)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::expr::Expr;
+    use crate::ssa::gen::emit;
+    use rand::prelude::*;
+
+    const INIT: u64 = 0;
+
+    #[test]
+    fn lower_eval_expr_eq() {
+        let mut rng = thread_rng();
+        let mut failed = Vec::new();
+
+        'exprs: for _ in 0..1000 {
+            let expr = Expr::rand(&mut rng);
+            let func = Function::from(&expr);
+            let opt = func.optimize();
+            let prog = emit(&opt);
+
+            for _ in 0..100 {
+                let bytes: Vec<u8> = (0..10).map(|_| rng.gen()).collect();
+                let expected = expr.hash_bytes(INIT, &bytes);
+
+                if expected != func.hash_bytes(INIT, &bytes)
+                    || expected != opt.hash_bytes(INIT, &bytes)
+                    || expected != prog.hash_bytes(INIT, &bytes)
+                {
+                    failed.push(expr.clone());
+                    continue 'exprs;
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            failed.sort_by_key(Expr::len);
+
+            let smallest = &failed[0];
+            let opt = Function::from(smallest).optimize();
+            println!(
+                "{}\nlowered to\n{}\nand emitted\n{}",
+                smallest,
+                opt,
+                emit(&opt)
+            );
+
+            panic!("{} expressions failed", failed.len());
+        }
+    }
+}
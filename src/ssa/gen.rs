@@ -0,0 +1,143 @@
+use super::analysis::{last_uses, Dominance};
+use super::code::{BinOp, Function, Instruction as Ssa, Var};
+use crate::bytecode::code::{Instruction, Memory, Program, Value};
+use std::collections::BTreeSet;
+
+/// the first memory slot that can hold an intermediate value, the ones before it are reserved for
+/// the hash state, the byte being hashed, the result and the trash register for rotations
+const FIRST_SLOT: Memory = 4;
+
+/// the x86 backend sign extends 32 bit immediates for adds and xors, so only immediates that fit
+/// in 31 bits mean the same thing to the interpreter and the jit
+const MAX_IMMEDIATE: u64 = i32::MAX as u64;
+
+/// the location of an ssa variable while emitting bytecode
+#[derive(Copy, Clone)]
+enum Location {
+    /// a constant that has not been loaded into memory
+    Const(u64),
+    Slot(Memory),
+    Unassigned,
+}
+
+struct Slots {
+    free: BTreeSet<Memory>,
+    next: Memory,
+}
+
+impl Slots {
+    fn alloc(&mut self) -> Memory {
+        if let Some(slot) = self.free.pop_first() {
+            slot
+        } else {
+            self.next += 1;
+            self.next - 1
+        }
+    }
+
+    fn free(&mut self, slot: Memory) {
+        if slot >= FIRST_SLOT {
+            self.free.insert(slot);
+        }
+    }
+}
+
+/// lower a function to bytecode, allocating memory slots by the live ranges of its variables
+pub fn emit(func: &Function) -> Program {
+    debug_assert!(Dominance::new(func).is_valid(func));
+
+    let last = last_uses(func);
+    let needs_slot = large_operands(func);
+
+    let mut instructions = Vec::new();
+    let mut locs = vec![Location::Unassigned; func.len()];
+    let mut slots = Slots {
+        free: BTreeSet::new(),
+        next: FIRST_SLOT,
+    };
+
+    for (var, instr) in func.instructions.iter().enumerate() {
+        if last[var].is_none() {
+            continue;
+        }
+
+        locs[var] = match *instr {
+            Ssa::HashState => Location::Slot(0),
+            Ssa::Byte => Location::Slot(1),
+            Ssa::Const(num) if needs_slot[var] => {
+                let slot = slots.alloc();
+                instructions.push(Instruction::MoveAbs(slot, num));
+                Location::Slot(slot)
+            }
+            Ssa::Const(num) => Location::Const(num),
+            Ssa::Binary(op, a, b) => {
+                let dies = |v: Var| last[v] == Some(var);
+                let src = operand(op, locs[b]);
+
+                let dst = match locs[a] {
+                    Location::Slot(slot) if slot >= FIRST_SLOT && dies(a) => slot,
+                    loc => {
+                        let dst = slots.alloc();
+                        instructions.push(load(dst, loc));
+                        dst
+                    }
+                };
+                instructions.push(binary(op, dst, src));
+
+                if let (Location::Slot(slot), true) = (locs[b], dies(b) && a != b) {
+                    slots.free(slot);
+                }
+                Location::Slot(dst)
+            }
+        };
+    }
+
+    instructions.push(load(2, locs[func.result]));
+
+    Program::new(instructions, Value::Reference(2))
+}
+
+/// find the constants that are used as the right hand side of an add or xor, but are too large
+/// to be immediates
+fn large_operands(func: &Function) -> Vec<bool> {
+    let mut large = vec![false; func.len()];
+
+    for instr in &func.instructions {
+        if let Ssa::Binary(BinOp::Add | BinOp::Xor, _, b) = instr {
+            if let Ssa::Const(num) = func.instructions[*b] {
+                large[*b] |= num > MAX_IMMEDIATE;
+            }
+        }
+    }
+
+    large
+}
+
+fn operand(op: BinOp, loc: Location) -> Value {
+    match loc {
+        Location::Const(num) if op.is_rotation() => Value::Immediate((num % 64) as u32),
+        Location::Const(num) => Value::Immediate(num as u32),
+        Location::Slot(slot) => Value::Reference(slot),
+        Location::Unassigned => unreachable!("operand used before it was defined"),
+    }
+}
+
+fn load(dst: Memory, loc: Location) -> Instruction {
+    match loc {
+        Location::Const(num) => match u32::try_from(num) {
+            Ok(small) => Instruction::Move(dst, Value::Immediate(small)),
+            Err(_) => Instruction::MoveAbs(dst, num),
+        },
+        Location::Slot(slot) => Instruction::Move(dst, Value::Reference(slot)),
+        Location::Unassigned => unreachable!("operand used before it was defined"),
+    }
+}
+
+fn binary(op: BinOp, dst: Memory, src: Value) -> Instruction {
+    match op {
+        BinOp::Add => Instruction::Add(dst, src),
+        BinOp::Xor => Instruction::Xor(dst, src),
+        BinOp::RotLeft => Instruction::RotLeft(dst, src),
+        BinOp::RotRight => Instruction::RotRight(dst, src),
+    }
+}
@@ -0,0 +1,36 @@
+use super::code::{BinOp, Function, Instruction, Var};
+use crate::expr::expr::{Expr, Tag};
+
+impl From<&Expr<Tag>> for Function {
+    fn from(expr: &Expr<Tag>) -> Self {
+        let mut instructions = Vec::new();
+        let result = lower_expr(expr, &mut instructions);
+
+        Function {
+            instructions,
+            result,
+        }
+    }
+}
+
+fn lower_expr(expr: &Expr<Tag>, instructions: &mut Vec<Instruction>) -> Var {
+    let instr = match expr {
+        Expr::Add(a, b) | Expr::Xor(a, b) | Expr::RotLeft(a, b) | Expr::RotRight(a, b) => {
+            let op = match expr {
+                Expr::Add(_, _) => BinOp::Add,
+                Expr::Xor(_, _) => BinOp::Xor,
+                Expr::RotLeft(_, _) => BinOp::RotLeft,
+                _ => BinOp::RotRight,
+            };
+            let a = lower_expr(a, instructions);
+            let b = lower_expr(b, instructions);
+            Instruction::Binary(op, a, b)
+        }
+        Expr::Tag(Tag::Const(num)) => Instruction::Const(*num),
+        Expr::Tag(Tag::HashState) => Instruction::HashState,
+        Expr::Tag(Tag::Byte) => Instruction::Byte,
+    };
+
+    instructions.push(instr);
+    instructions.len() - 1
+}
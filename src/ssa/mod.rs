@@ -0,0 +1,5 @@
+pub mod analysis;
+pub mod code;
+pub mod gen;
+pub mod lower;
+pub mod opt;
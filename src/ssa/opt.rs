@@ -0,0 +1,262 @@
+use super::analysis::{constants, live};
+use super::code::{BinOp, Function, Instruction, Var};
+use std::collections::HashMap;
+
+impl Function {
+    /// run every optimization pass over the function
+    pub fn optimize(&self) -> Function {
+        schedule(&value_number(&fold_constants(self)))
+    }
+}
+
+/// replace every variable with a known value by a constant, and remove identity operations
+pub fn fold_constants(func: &Function) -> Function {
+    let known = constants(func);
+    let mut renames: Vec<Var> = Vec::with_capacity(func.len());
+    let mut instructions = Vec::with_capacity(func.len());
+
+    for (var, instr) in func.instructions.iter().enumerate() {
+        let folded = match (known[var], *instr) {
+            (Some(num), _) => Instruction::Const(num),
+            (None, Instruction::Binary(op, a, b)) => {
+                // `known` is indexed by the variables of `func`, so look the operands up before
+                // renaming them
+                let (known_a, known_b) = (known[a], known[b]);
+                let (a, b) = (renames[a], renames[b]);
+
+                match (op, known_b) {
+                    (BinOp::Add | BinOp::Xor, Some(0)) => {
+                        renames.push(a);
+                        continue;
+                    }
+                    (BinOp::RotLeft | BinOp::RotRight, Some(num)) if num % 64 == 0 => {
+                        renames.push(a);
+                        continue;
+                    }
+                    (BinOp::Add | BinOp::Xor, _) if known_a == Some(0) => {
+                        renames.push(b);
+                        continue;
+                    }
+                    _ => Instruction::Binary(op, a, b),
+                }
+            }
+            (None, instr) => instr,
+        };
+
+        renames.push(instructions.len());
+        instructions.push(folded);
+    }
+
+    Function {
+        instructions,
+        result: renames[func.result],
+    }
+}
+
+/// merge instructions that compute the same value (common subexpression elimination)
+pub fn value_number(func: &Function) -> Function {
+    let mut numbers: HashMap<Instruction, Var> = HashMap::new();
+    let mut renames = Vec::with_capacity(func.len());
+    let mut instructions = Vec::new();
+
+    for instr in &func.instructions {
+        let instr = match *instr {
+            Instruction::Binary(op, a, b) => {
+                let (a, b) = (renames[a], renames[b]);
+                if op.is_commutative() && b < a {
+                    Instruction::Binary(op, b, a)
+                } else {
+                    Instruction::Binary(op, a, b)
+                }
+            }
+            instr => instr,
+        };
+
+        let var = *numbers.entry(instr).or_insert_with(|| {
+            instructions.push(instr);
+            instructions.len() - 1
+        });
+        renames.push(var);
+    }
+
+    Function {
+        instructions,
+        result: renames[func.result],
+    }
+}
+
+/// reorder a function so that the operand needing the most registers is computed first, which
+/// keeps the number of simultaneously live variables low, and drop dead instructions
+pub fn schedule(func: &Function) -> Function {
+    let live = live(func);
+    let mut need = vec![0usize; func.len()];
+
+    for (var, instr) in func.instructions.iter().enumerate() {
+        if let (true, Some((a, b))) = (live[var], instr.operands()) {
+            need[var] = if need[a] == need[b] {
+                need[a] + 1
+            } else {
+                need[a].max(need[b])
+            };
+        }
+    }
+
+    let mut renames = vec![None; func.len()];
+    let mut instructions = Vec::new();
+    visit(func, func.result, &need, &mut renames, &mut instructions);
+
+    Function {
+        instructions,
+        result: renames[func.result].unwrap(),
+    }
+}
+
+fn visit(
+    func: &Function,
+    var: Var,
+    need: &[usize],
+    renames: &mut [Option<Var>],
+    instructions: &mut Vec<Instruction>,
+) -> Var {
+    if let Some(new) = renames[var] {
+        return new;
+    }
+
+    let instr = match func.instructions[var] {
+        Instruction::Binary(op, a, b) => {
+            let (a, b) = if need[b] > need[a] {
+                let b = visit(func, b, need, renames, instructions);
+                (visit(func, a, need, renames, instructions), b)
+            } else {
+                let a = visit(func, a, need, renames, instructions);
+                (a, visit(func, b, need, renames, instructions))
+            };
+            Instruction::Binary(op, a, b)
+        }
+        instr => instr,
+    };
+
+    instructions.push(instr);
+    renames[var] = Some(instructions.len() - 1);
+    instructions.len() - 1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bytecode::verify::{Target, JIT_MAX_SLOT};
+    use crate::expr::expr::{Expr, Tag};
+    use crate::expr::parse::parse;
+    use crate::ssa::gen::emit;
+    use rand::prelude::*;
+
+    fn lower(text: &str) -> Function {
+        Function::from(&parse(text).unwrap())
+    }
+
+    fn same_hash(a: &Function, b: &Function) -> bool {
+        let mut rng = thread_rng();
+        (0..100).all(|_| {
+            let (hash_state, byte) = rng.gen();
+            a.eval(hash_state, byte) == b.eval(hash_state, byte)
+        })
+    }
+
+    /// a balanced tree of `depth` levels of alternating operators, over the state and the byte
+    fn balanced(depth: usize) -> Expr<Tag> {
+        if depth == 0 {
+            return Expr::Tag(Tag::HashState);
+        }
+        let (a, b) = (Box::new(balanced(depth - 1)), Box::new(balanced(depth - 1)));
+        match depth % 3 {
+            0 => Expr::Add(a, Box::new(Expr::Xor(b, Box::new(Expr::Tag(Tag::Byte))))),
+            1 => Expr::Xor(a, b),
+            _ => Expr::Add(a, b),
+        }
+    }
+
+    #[test]
+    fn folds_identities() {
+        let func = lower("((state xor 0) + (byte xor (7 xor 7)))");
+        let folded = fold_constants(&func);
+
+        assert_eq!(
+            folded.instructions[folded.result],
+            Instruction::Binary(BinOp::Add, 0, 2),
+            "{}",
+            folded
+        );
+        assert_eq!(folded.instructions[0], Instruction::HashState);
+        assert_eq!(folded.instructions[2], Instruction::Byte);
+        assert!(same_hash(&func, &folded));
+
+        // the constants that were folded into others are left for `schedule` to drop
+        let folded = fold_constants(&lower("((state >> 64) xor (3 + 4))"));
+        match folded.instructions[folded.result] {
+            Instruction::Binary(BinOp::Xor, 0, b) => {
+                assert_eq!(folded.instructions[b], Instruction::Const(7));
+            }
+            instr => panic!("{} in\n{}", instr, folded),
+        }
+    }
+
+    #[test]
+    fn merges_common_subexpressions() {
+        let func = lower("((state + byte) xor ((byte + state) << 5))");
+        let numbered = value_number(&func);
+
+        assert_eq!(
+            numbered
+                .instructions
+                .iter()
+                .filter(|instr| matches!(instr, Instruction::Binary(BinOp::Add, _, _)))
+                .count(),
+            1,
+            "{}",
+            numbered
+        );
+        assert_eq!(numbered.len(), 6);
+        assert!(same_hash(&func, &numbered));
+    }
+
+    /// `(state + byte) xor ((state + byte) xor ...)` with `depth` operators nested on the right,
+    /// which keeps every left operand live when computed left to right
+    fn comb(depth: usize) -> Expr<Tag> {
+        let leaf = Expr::Add(
+            Box::new(Expr::Tag(Tag::HashState)),
+            Box::new(Expr::Tag(Tag::Byte)),
+        );
+        (0..depth).fold(leaf.clone(), |expr, _| {
+            Expr::Xor(Box::new(leaf.clone()), Box::new(expr))
+        })
+    }
+
+    #[test]
+    fn schedule_keeps_slots_low() {
+        for (expr, in_order) in [(balanced(9), true), (comb(30), false)] {
+            let func = Function::from(&expr);
+            let scheduled = schedule(&func);
+            let prog = emit(&scheduled);
+
+            assert!(same_hash(&func, &scheduled));
+            assert!(prog.verify(Target::Jit).is_ok(), "{}", prog);
+            assert!(prog.biggest_ptr() <= JIT_MAX_SLOT, "{}", prog.biggest_ptr());
+            assert_eq!(
+                emit(&func).biggest_ptr() <= JIT_MAX_SLOT,
+                in_order,
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn schedule_drops_dead_code() {
+        let mut func = lower("(state + byte)");
+        func.instructions.push(Instruction::Const(1));
+        let scheduled = schedule(&func);
+
+        assert_eq!(scheduled.len(), 3);
+        assert!(same_hash(&func, &scheduled));
+    }
+}